@@ -1 +1,2 @@
+pub mod escape;
 pub mod types;
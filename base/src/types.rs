@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 pub struct Category {
@@ -12,10 +13,246 @@ pub struct Post {
     pub title: String,
     pub topic: Option<String>,
     pub room_id: String,
+    pub author: String,
+    pub creation_time: u64,
+    pub last_activity_time: u64,
+    pub comment_count: usize,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PostSort {
+    #[default]
+    Activity,
+    Created,
+    Replies,
+}
+
+impl PostSort {
+    pub const ALL: [PostSort; 3] = [PostSort::Activity, PostSort::Created, PostSort::Replies];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PostSort::Activity => "activity",
+            PostSort::Created => "created",
+            PostSort::Replies => "replies",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PostSort::Activity => "Latest activity",
+            PostSort::Created => "Newest",
+            PostSort::Replies => "Most replies",
+        }
+    }
+
+    pub fn sort(&self, posts: &mut [Post]) {
+        match self {
+            PostSort::Activity => posts.sort_by_key(|post| Reverse(post.last_activity_time)),
+            PostSort::Created => posts.sort_by_key(|post| Reverse(post.creation_time)),
+            PostSort::Replies => posts.sort_by_key(|post| Reverse(post.comment_count)),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 pub struct Comment {
+    pub event_id: String,
     pub html: String,
+    #[serde(default)]
+    pub kind: CommentKind,
     pub sender: String,
+    pub sender_name: String,
+    pub sender_avatar_url: Option<String>,
+    pub origin_server_ts: u64,
+    pub last_edit_time: Option<u64>,
+    pub in_reply_to: Option<String>,
+    pub reply_quote: Option<Quote>,
+    pub thread_root: Option<String>,
+    #[serde(default)]
+    pub thread_replies: Vec<Comment>,
+    pub redaction: Option<Redaction>,
+    #[serde(default)]
+    pub revisions: Vec<Revision>,
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, Default)]
+pub enum CommentKind {
+    #[default]
+    Message,
+    Notice,
+    Emote,
+    Location {
+        description: String,
+        geo_uri: String,
+        map_url: Option<String>,
+    },
+    Poll(Poll),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct Poll {
+    pub question: String,
+    pub answers: Vec<PollAnswer>,
+    pub voters: usize,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct PollAnswer {
+    pub id: String,
+    pub text: String,
+    pub votes: usize,
+}
+
+impl Comment {
+    pub fn find<'a>(comments: &'a [Comment], event_id: &str) -> Option<&'a Comment> {
+        comments.iter().find_map(|comment| {
+            if comment.event_id == event_id {
+                Some(comment)
+            } else {
+                Self::find(&comment.thread_replies, event_id)
+            }
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct Revision {
+    pub body: String,
+    pub origin_server_ts: u64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum DiffTag {
+    Equal,
+    Delete,
+    Insert,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct DiffLine {
+    pub tag: DiffTag,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct Redaction {
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct Reaction {
+    pub key: String,
+    pub senders: Vec<ReactionSender>,
+}
+
+impl Reaction {
+    pub fn count(&self) -> usize {
+        self.senders.len()
+    }
+
+    pub fn reacted_by(&self, user_id: &str) -> bool {
+        self.senders.iter().any(|sender| sender.user_id == user_id)
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct ReactionSender {
+    pub user_id: String,
+    pub event_id: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct Quote {
+    pub sender_name: String,
+    pub snippet: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct PostEvent {
+    pub room_id: String,
+    pub comment: Comment,
+}
+
+/// A comment as rendered on the post page, sent to open pages when it changes.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct RenderedComment {
+    pub event_id: String,
+    pub html: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct SearchResult {
+    pub room_id: String,
+    pub event_id: Option<String>,
+    pub post_title: String,
+    pub category: Option<Category>,
+    pub sender: String,
+    pub sender_name: String,
+    pub snippet: String,
+    pub origin_server_ts: u64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: usize,
+    pub total_pages: usize,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, page: usize, per_page: usize) -> Self {
+        let total_pages = ((items.len() + per_page - 1) / per_page).max(1);
+        let page = page.clamp(1, total_pages);
+
+        let items = items
+            .into_iter()
+            .skip((page - 1) * per_page)
+            .take(per_page)
+            .collect();
+
+        Self {
+            items,
+            page,
+            total_pages,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_splits_items() {
+        let page = Page::new((1..=7).collect(), 2, 3);
+        assert_eq!(page.items, vec![4, 5, 6]);
+        assert_eq!(page.page, 2);
+        assert_eq!(page.total_pages, 3);
+
+        let page = Page::new((1..=7).collect(), 3, 3);
+        assert_eq!(page.items, vec![7]);
+    }
+
+    #[test]
+    fn page_clamps_out_of_range() {
+        let page = Page::new((1..=6).collect(), 9, 3);
+        assert_eq!(page.items, vec![4, 5, 6]);
+        assert_eq!(page.page, 2);
+        assert_eq!(page.total_pages, 2);
+
+        let page = Page::new((1..=6).collect(), 0, 3);
+        assert_eq!(page.page, 1);
+    }
+
+    #[test]
+    fn page_of_nothing_has_one_page() {
+        let page = Page::<u32>::new(Vec::new(), 1, 3);
+        assert!(page.items.is_empty());
+        assert_eq!(page.page, 1);
+        assert_eq!(page.total_pages, 1);
+    }
 }
@@ -1,8 +1,189 @@
+use crate::Error;
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
 pub struct Config {
     pub homeserver_url: String,
     pub username: String,
     pub password: String,
+    /// Secret signing session cookies. Logging in is disabled without one.
+    #[serde(default)]
+    pub session_secret: Option<String>,
+    #[serde(default = "default_forum_alias")]
+    pub forum_alias: String,
+    #[serde(default = "default_category_alias_prefix")]
+    pub category_alias_prefix: String,
+    #[serde(default = "default_category_alias_suffix")]
+    pub category_alias_suffix: String,
+    #[serde(default = "default_via_servers")]
+    pub via_servers: Vec<String>,
+    #[serde(default = "default_public_url")]
+    pub public_url: String,
+    #[serde(default = "default_listen")]
+    pub listen: SocketAddr,
+    #[serde(default)]
+    pub unix_socket: Option<PathBuf>,
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    #[serde(default = "default_index_path")]
+    pub index_path: PathBuf,
+    #[serde(default = "default_media_cache_path")]
+    pub media_cache_path: PathBuf,
+    #[serde(default = "default_media_cache_size")]
+    pub media_cache_size: u64,
+    #[serde(default)]
+    pub log: LogConfig,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct LogConfig {
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    #[serde(default)]
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: default_log_level(),
+            format: LogFormat::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+fn default_forum_alias() -> String {
+    "#forum:corepaper.org".to_owned()
+}
+
+fn default_category_alias_prefix() -> String {
+    "#forum-".to_owned()
+}
+
+fn default_category_alias_suffix() -> String {
+    ":corepaper.org".to_owned()
+}
+
+fn default_via_servers() -> Vec<String> {
+    vec!["corepaper.org".to_owned()]
+}
+
+fn default_listen() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 8080))
+}
+
+fn default_public_url() -> String {
+    "http://127.0.0.1:8080".to_owned()
+}
+
+fn default_log_level() -> String {
+    "info".to_owned()
+}
+
+fn default_index_path() -> PathBuf {
+    PathBuf::from("morum-index")
+}
+
+fn default_media_cache_path() -> PathBuf {
+    PathBuf::from("morum-media")
+}
+
+fn default_media_cache_size() -> u64 {
+    1024 * 1024 * 1024
+}
+
+impl Config {
+    /// Checks settings that cannot be combined, before anything is started.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.unix_socket.is_some() && self.tls.is_some() {
+            return Err(Error::TlsOnUnixSocket);
+        }
+
+        Ok(())
+    }
+
+    pub fn category_alias(&self, room_local_id: &str) -> String {
+        format!(
+            "{}{}{}",
+            self.category_alias_prefix, room_local_id, self.category_alias_suffix
+        )
+    }
+
+    pub fn category_local_id(&self, room_alias: &str) -> Option<String> {
+        room_alias
+            .strip_prefix(&self.category_alias_prefix)
+            .and_then(|rest| rest.strip_suffix(&self.category_alias_suffix))
+            .filter(|local_id| !local_id.is_empty())
+            .map(|local_id| local_id.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_to_corepaper_aliases() {
+        let config: Config = serde_yaml::from_str(
+            "homeserver_url: https://matrix.corepaper.org\n\
+             username: morum\n\
+             password: secret\n\
+             session_secret: secret\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.forum_alias, "#forum:corepaper.org");
+        assert_eq!(config.category_alias("dev"), "#forum-dev:corepaper.org");
+        assert_eq!(config.via_servers, vec!["corepaper.org".to_owned()]);
+    }
+
+    #[test]
+    fn parses_category_local_id() {
+        let config: Config = serde_yaml::from_str(
+            "homeserver_url: https://matrix.corepaper.org\n\
+             username: morum\n\
+             password: secret\n\
+             session_secret: secret\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.category_local_id("#forum-dev:corepaper.org"),
+            Some("dev".to_owned())
+        );
+        assert_eq!(config.category_local_id("#forum-:corepaper.org"), None);
+        assert_eq!(config.category_local_id("#forum:corepaper.org"), None);
+        assert_eq!(config.category_local_id("#other-dev:example.org"), None);
+    }
+
+    #[test]
+    fn rejects_tls_on_unix_socket() {
+        let config: Config = serde_yaml::from_str(
+            "homeserver_url: https://matrix.corepaper.org\n\
+             username: morum\n\
+             password: secret\n\
+             session_secret: secret\n\
+             unix_socket: /run/morum.sock\n\
+             tls:\n  cert_path: cert.pem\n  key_path: key.pem\n",
+        )
+        .unwrap();
+
+        assert!(matches!(config.validate(), Err(Error::TlsOnUnixSocket)));
+    }
 }
@@ -15,7 +15,7 @@ pub enum Error {
     #[error("Url parsing error")]
     UrlParse(#[from] url::ParseError),
     #[error("Ruma client error")]
-    RumaClient(Box<dyn std::error::Error>),
+    RumaClient(#[source] Box<dyn std::error::Error>),
     #[error("Matrix client build error")]
     MatrixClientBuild(#[from] matrix_sdk::ClientBuildError),
     #[error("Matrix error")]
@@ -26,11 +26,25 @@ pub enum Error {
     MatrixIdParse(#[from] ruma::IdParseError),
     #[error("System time error")]
     SystemTime(#[from] std::time::SystemTimeError),
+    #[error("Time format error")]
+    TimeFormat(#[from] time::error::Format),
     #[error("Hyper error")]
     Hyper(#[from] hyper::Error),
     #[error("Axum form error")]
     AxumForm(#[from] axum::extract::rejection::FormRejection),
+    #[error("Axum query error")]
+    AxumQuery(#[from] axum::extract::rejection::QueryRejection),
+    #[error("TLS error")]
+    Tls(#[from] tokio_rustls::rustls::Error),
+    #[error("Index database error")]
+    Sled(#[from] sled::Error),
+    #[error("Task join error")]
+    Join(#[from] tokio::task::JoinError),
+    #[error("Log filter parse error")]
+    LogFilter(#[from] tracing_subscriber::filter::ParseError),
 
+    #[error("Login is disabled as no session secret is configured")]
+    LoginDisabled,
     #[error("Already logged in")]
     AlreadyLoggedIn,
     #[error("Require logging in")]
@@ -41,6 +55,10 @@ pub enum Error {
     UnknownCategory,
     #[error("Unknown post")]
     UnknownPost,
+    #[error("Unknown comment")]
+    UnknownComment,
+    #[error("Unknown media")]
+    UnknownMedia,
     #[error("Unknown post title")]
     UnknownPostTitle,
     #[error("Unknown category title")]
@@ -51,8 +69,22 @@ pub enum Error {
     UnknownToplevelRoom,
     #[error("Unknown category room")]
     UnknownCategoryRoom,
-    #[error("Unknown category room")]
+    #[error("Invalid category alias")]
     InvalidCategoryAlias,
+    #[error("Comment is empty")]
+    EmptyComment,
+    #[error("Reaction is empty")]
+    EmptyReaction,
+    #[error("Post title is empty")]
+    EmptyPostTitle,
+    #[error("No private key found for TLS")]
+    InvalidTlsKey,
+    #[error("Invalid via server in config")]
+    InvalidViaServer(#[source] ruma::IdParseError),
+    #[error("TLS is not supported on unix sockets")]
+    TlsOnUnixSocket,
+    #[error("Unix socket path exists and is not a socket")]
+    UnixSocketPathInUse,
 }
 
 impl From<std::convert::Infallible> for Error {
@@ -69,11 +101,24 @@ impl<R: std::error::Error + 'static> From<ruma::client::Error<matrix_sdk::reqwes
     }
 }
 
+impl Error {
+    pub fn chain(&self) -> String {
+        let mut chain = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(err) = source {
+            chain.push_str(": ");
+            chain.push_str(&err.to_string());
+            source = err.source();
+        }
+        chain
+    }
+}
+
 impl From<Error> for std::io::Error {
     fn from(err: Error) -> std::io::Error {
-        match dbg!(err) {
+        match err {
             Error::Io(err) => err,
-            err => std::io::Error::new(std::io::ErrorKind::Other, err.to_string()),
+            err => std::io::Error::new(std::io::ErrorKind::Other, err.chain()),
         }
     }
 }
@@ -0,0 +1,370 @@
+use crate::Error;
+use morum_base::types;
+use ruma::events::AnySyncTimelineEvent;
+use ruma::serde::Raw;
+use ruma::OwnedEventId;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub enum RoomKind {
+    Toplevel,
+    Category { room_alias: String },
+    Post { category_room_alias: String },
+}
+
+/// Raw timeline of a post room in chronological order.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PostTimeline {
+    pub events: Vec<Raw<AnySyncTimelineEvent>>,
+    /// Relations of the events as last seen unredacted, as redaction strips them from the content.
+    #[serde(default)]
+    pub relations: HashMap<OwnedEventId, EventRelation>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub enum EventRelation {
+    Reply {
+        in_reply_to: OwnedEventId,
+    },
+    Thread {
+        thread_root: OwnedEventId,
+        in_reply_to: Option<OwnedEventId>,
+    },
+    Replacement {
+        event_id: OwnedEventId,
+    },
+    Annotation {
+        event_id: OwnedEventId,
+    },
+    Reference {
+        event_id: OwnedEventId,
+    },
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct UserSession {
+    pub user_id: String,
+    pub device_id: String,
+    pub access_token: String,
+    pub expires_at: u64,
+}
+
+impl UserSession {
+    fn key(&self) -> Vec<u8> {
+        session_key(&self.user_id, &self.device_id)
+    }
+}
+
+fn session_key(user_id: &str, device_id: &str) -> Vec<u8> {
+    [user_id.as_bytes(), SEPARATOR, device_id.as_bytes()].concat()
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct SearchDocument {
+    pub room_id: String,
+    pub event_id: Option<String>,
+    pub category_room_local_id: Option<String>,
+    pub post_title: String,
+    pub sender: String,
+    pub sender_name: String,
+    pub text: String,
+    pub origin_server_ts: u64,
+}
+
+impl SearchDocument {
+    fn key(&self) -> Vec<u8> {
+        [
+            self.room_id.as_bytes(),
+            SEPARATOR,
+            self.event_id.as_deref().unwrap_or_default().as_bytes(),
+        ]
+        .concat()
+    }
+
+    fn terms(&self) -> BTreeSet<String> {
+        let mut terms = tokenize(&self.text);
+        if self.event_id.is_none() {
+            terms.extend(tokenize(&self.post_title));
+        }
+        terms
+    }
+}
+
+pub fn tokenize(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+fn term_key(term: &str, document_key: &[u8]) -> Vec<u8> {
+    [term.as_bytes(), SEPARATOR, document_key].concat()
+}
+
+#[derive(Clone)]
+pub struct Index {
+    categories: sled::Tree,
+    category_posts: sled::Tree,
+    media: sled::Tree,
+    post_comments: sled::Tree,
+    post_timelines: sled::Tree,
+    rooms: sled::Tree,
+    search_documents: sled::Tree,
+    search_terms: sled::Tree,
+    sessions: sled::Tree,
+}
+
+const CATEGORIES_KEY: &str = "categories";
+const SEPARATOR: &[u8] = b"\0";
+
+fn get<T: DeserializeOwned>(tree: &sled::Tree, key: &str) -> Result<Option<T>, Error> {
+    Ok(match tree.get(key)? {
+        Some(value) => Some(serde_json::from_slice(&value)?),
+        None => None,
+    })
+}
+
+fn set<T: Serialize + ?Sized>(tree: &sled::Tree, key: &str, value: &T) -> Result<(), Error> {
+    tree.insert(key, serde_json::to_vec(value)?)?;
+    Ok(())
+}
+
+impl Index {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let db = sled::open(path)?;
+
+        Ok(Self {
+            categories: db.open_tree("categories")?,
+            category_posts: db.open_tree("category_posts")?,
+            media: db.open_tree("media")?,
+            post_comments: db.open_tree("post_comments")?,
+            post_timelines: db.open_tree("post_timelines")?,
+            rooms: db.open_tree("rooms")?,
+            search_documents: db.open_tree("search_documents")?,
+            search_terms: db.open_tree("search_terms")?,
+            sessions: db.open_tree("sessions")?,
+        })
+    }
+
+    pub fn categories(&self) -> Result<Option<Vec<types::Category>>, Error> {
+        get(&self.categories, CATEGORIES_KEY)
+    }
+
+    pub fn set_categories(&self, categories: &[types::Category]) -> Result<(), Error> {
+        set(&self.categories, CATEGORIES_KEY, categories)
+    }
+
+    pub fn category_posts(
+        &self,
+        room_alias: &str,
+    ) -> Result<Option<(types::Category, Vec<types::Post>)>, Error> {
+        get(&self.category_posts, room_alias)
+    }
+
+    pub fn set_category_posts(
+        &self,
+        room_alias: &str,
+        category_posts: &(types::Category, Vec<types::Post>),
+    ) -> Result<(), Error> {
+        set(&self.category_posts, room_alias, category_posts)
+    }
+
+    pub fn post_comments(
+        &self,
+        room_id: &str,
+    ) -> Result<Option<(types::Post, Vec<types::Comment>)>, Error> {
+        get(&self.post_comments, room_id)
+    }
+
+    pub fn set_post_comments(
+        &self,
+        room_id: &str,
+        post_comments: &(types::Post, Vec<types::Comment>),
+    ) -> Result<(), Error> {
+        set(&self.post_comments, room_id, post_comments)
+    }
+
+    pub fn post_room_ids(&self) -> Result<Vec<String>, Error> {
+        self.post_comments
+            .iter()
+            .keys()
+            .map(|key| Ok(String::from_utf8_lossy(&key?).into_owned()))
+            .collect()
+    }
+
+    pub fn post_timeline(&self, room_id: &str) -> Result<Option<PostTimeline>, Error> {
+        get(&self.post_timelines, room_id)
+    }
+
+    pub fn set_post_timeline(&self, room_id: &str, timeline: &PostTimeline) -> Result<(), Error> {
+        set(&self.post_timelines, room_id, timeline)
+    }
+
+    pub fn add_media(&self, server_name: &str, media_id: &str) -> Result<(), Error> {
+        self.media
+            .insert([server_name, media_id].join("/"), Vec::new())?;
+        Ok(())
+    }
+
+    pub fn has_media(&self, server_name: &str, media_id: &str) -> Result<bool, Error> {
+        Ok(self.media.contains_key([server_name, media_id].join("/"))?)
+    }
+
+    pub fn room_kind(&self, room_id: &str) -> Result<Option<RoomKind>, Error> {
+        get(&self.rooms, room_id)
+    }
+
+    pub fn set_room_kind(&self, room_id: &str, kind: &RoomKind) -> Result<(), Error> {
+        set(&self.rooms, room_id, kind)
+    }
+
+    pub fn session(&self, user_id: &str, device_id: &str) -> Result<Option<UserSession>, Error> {
+        Ok(match self.sessions.get(session_key(user_id, device_id))? {
+            Some(value) => Some(serde_json::from_slice(&value)?),
+            None => None,
+        })
+    }
+
+    pub fn sessions(&self) -> Result<Vec<UserSession>, Error> {
+        self.sessions
+            .iter()
+            .values()
+            .map(|value| Ok(serde_json::from_slice(&value?)?))
+            .collect()
+    }
+
+    pub fn set_session(&self, session: &UserSession) -> Result<(), Error> {
+        self.sessions
+            .insert(session.key(), serde_json::to_vec(session)?)?;
+        Ok(())
+    }
+
+    pub fn remove_session(&self, user_id: &str, device_id: &str) -> Result<(), Error> {
+        self.sessions.remove(session_key(user_id, device_id))?;
+        Ok(())
+    }
+
+    pub fn set_search_documents(
+        &self,
+        room_id: &str,
+        documents: &[SearchDocument],
+    ) -> Result<(), Error> {
+        let prefix = [room_id.as_bytes(), SEPARATOR].concat();
+        for item in self.search_documents.scan_prefix(&prefix) {
+            let (key, value) = item?;
+            let document: SearchDocument = serde_json::from_slice(&value)?;
+            for term in document.terms() {
+                self.search_terms.remove(term_key(&term, &key))?;
+            }
+            self.search_documents.remove(key)?;
+        }
+
+        for document in documents {
+            let key = document.key();
+            self.search_documents
+                .insert(&key, serde_json::to_vec(document)?)?;
+            for term in document.terms() {
+                self.search_terms
+                    .insert(term_key(&term, &key), Vec::new())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn search(&self, terms: &BTreeSet<String>) -> Result<Vec<SearchDocument>, Error> {
+        let mut matches: Option<BTreeSet<Vec<u8>>> = None;
+        for term in terms {
+            let prefix = term_key(term, &[]);
+            let keys = self
+                .search_terms
+                .scan_prefix(&prefix)
+                .keys()
+                .map(|key| key.map(|key| key[prefix.len()..].to_vec()))
+                .collect::<Result<BTreeSet<_>, _>>()?;
+
+            matches = Some(match matches {
+                Some(matches) => matches.intersection(&keys).cloned().collect(),
+                None => keys,
+            });
+        }
+
+        let mut documents = Vec::new();
+        for key in matches.unwrap_or_default() {
+            if let Some(value) = self.search_documents.get(key)? {
+                documents.push(serde_json::from_slice(&value)?);
+            }
+        }
+
+        Ok(documents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(room_id: &str, event_id: Option<&str>, text: &str) -> SearchDocument {
+        SearchDocument {
+            room_id: room_id.to_owned(),
+            event_id: event_id.map(|event_id| event_id.to_owned()),
+            category_room_local_id: None,
+            post_title: "Release planning".to_owned(),
+            sender: "@alice:example.org".to_owned(),
+            sender_name: "Alice".to_owned(),
+            text: text.to_owned(),
+            origin_server_ts: 0,
+        }
+    }
+
+    #[test]
+    fn tokenizes_into_lowercase_words() {
+        assert_eq!(
+            tokenize("Hello, hello WORLD! Ünïcode_42"),
+            ["hello", "world", "ünïcode", "42"]
+                .iter()
+                .map(|term| term.to_string())
+                .collect::<BTreeSet<_>>()
+        );
+    }
+
+    #[test]
+    fn searches_documents_matching_all_terms() {
+        let path = std::env::temp_dir().join(format!("morum-index-test-{}", std::process::id()));
+        let index = Index::open(&path).unwrap();
+
+        index
+            .set_search_documents(
+                "!a:example.org",
+                &[
+                    document("!a:example.org", None, "When do we ship?"),
+                    document("!a:example.org", Some("$1"), "Ship it on Friday"),
+                ],
+            )
+            .unwrap();
+        index
+            .set_search_documents(
+                "!b:example.org",
+                &[document("!b:example.org", Some("$2"), "Friday lunch")],
+            )
+            .unwrap();
+
+        let results = index.search(&tokenize("friday SHIP")).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].event_id.as_deref(), Some("$1"));
+
+        // Post titles are only indexed with the post itself.
+        let results = index.search(&tokenize("release")).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].event_id, None);
+
+        // Replacing the documents of a room drops its old terms.
+        index.set_search_documents("!a:example.org", &[]).unwrap();
+        assert!(index.search(&tokenize("ship")).unwrap().is_empty());
+
+        drop(index);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
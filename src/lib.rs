@@ -1,4 +1,5 @@
 mod error;
+mod index;
 
 pub mod config;
 pub mod matrix;
@@ -1,6 +1,8 @@
 use clap::Parser;
+use morum::config::{LogConfig, LogFormat};
 use morum::{Config, Error};
 use std::fs;
+use tracing_subscriber::EnvFilter;
 
 #[derive(Parser, Debug)]
 struct Args {
@@ -8,11 +10,32 @@ struct Args {
     config: String,
 }
 
+fn init_logging(config: &LogConfig) -> Result<(), Error> {
+    // RUST_LOG takes precedence over the configured level.
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::try_new(&config.level)?,
+    };
+
+    match config.format {
+        LogFormat::Text => tracing_subscriber::fmt().with_env_filter(filter).init(),
+        LogFormat::Json => tracing_subscriber::fmt()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_env_filter(filter)
+            .init(),
+    }
+
+    Ok(())
+}
+
 async fn run() -> Result<(), Error> {
     let args = Args::parse();
-    tracing_subscriber::fmt::init();
 
     let config: Config = serde_yaml::from_str(&fs::read_to_string(args.config)?)?;
+    config.validate()?;
+    init_logging(&config.log)?;
 
     let matrix = morum::matrix::start(config.clone()).await?;
 
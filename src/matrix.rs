@@ -1,105 +1,1598 @@
+use crate::index::{
+    tokenize, EventRelation, Index, PostTimeline, RoomKind, SearchDocument, UserSession,
+};
 use crate::{Config, Error};
-use matrix_sdk::Client;
-use matrix_sdk::{config::SyncSettings, room::MessagesOptions};
-use morum_base::types;
+use matrix_sdk::deserialized_responses::{JoinedRoom, SyncResponse};
+use matrix_sdk::{config::SyncSettings, room, room::MessagesOptions};
+use matrix_sdk::{Client, LoopCtrl};
+use morum_base::{escape::escape_html, types};
+use once_cell::sync::Lazy;
 use regex::Regex;
+use ruma::events::room::message::{
+    sanitize::{remove_plain_reply_fallback, HtmlSanitizerMode, RemoveReplyFallback},
+    FormattedBody, MessageFormat, MessageType,
+};
 use ruma::events::{
-    room::name::RoomNameEventContent, room::topic::RoomTopicEventContent, EmptyStateKey,
+    room::create::RoomCreateEventContent, room::name::RoomNameEventContent,
+    room::topic::RoomTopicEventContent, room::MediaSource, AnySyncTimelineEvent, EmptyStateKey,
     RedactContent, RedactedStateEventContent, StateEventContent, SyncStateEvent,
 };
 use ruma::serde::Raw;
-use ruma::{assign, OwnedRoomId, RoomAliasId, RoomId, RoomOrAliasId};
+use ruma::{
+    assign, DeviceId, EventId, MxcUri, OwnedDeviceId, OwnedEventId, OwnedMxcUri, OwnedRoomId,
+    OwnedServerName, OwnedUserId, RoomAliasId, RoomId, RoomOrAliasId, ServerName, TransactionId,
+    UserId,
+};
 use ruma_macros::EventContent;
 use serde::{Deserialize, Serialize};
-use tokio::task;
-use tracing::info;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tokio::sync::{broadcast, watch, Mutex, OwnedMutexGuard, RwLock};
+use tokio::task::{self, JoinHandle};
+use tracing::{info, warn};
 use url::Url;
 
-fn deserialize_sync_state_events_to_content<C>(
-    events: Vec<Raw<SyncStateEvent<C>>>,
-) -> Result<Option<C>, Error>
-where
-    C: StateEventContent + RedactContent,
-    C::Redacted: RedactedStateEventContent<StateKey = C::StateKey>,
-{
-    Ok(if let Some(event) = events.first() {
-        let event = event.deserialize()?;
+fn deserialize_sync_state_events_to_content<C>(
+    events: Vec<Raw<SyncStateEvent<C>>>,
+) -> Result<Option<C>, Error>
+where
+    C: StateEventContent + RedactContent,
+    C::Redacted: RedactedStateEventContent<StateKey = C::StateKey>,
+{
+    Ok(if let Some(event) = events.first() {
+        let event = event.deserialize()?;
+
+        if let SyncStateEvent::Original(event) = event {
+            Some(event.content)
+        } else {
+            None
+        }
+    } else {
+        None
+    })
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, EventContent)]
+#[ruma_event(type = "org.corepaper.morum.category", kind = State, state_key_type = EmptyStateKey)]
+pub struct MorumCategoryEventContent {
+    #[serde(default, deserialize_with = "ruma::serde::empty_string_as_none")]
+    pub category: Option<String>,
+}
+
+const QUOTE_SNIPPET_LENGTH: usize = 140;
+const POST_EVENTS_CAPACITY: usize = 64;
+const SEARCH_SNIPPET_CONTEXT: usize = 12;
+const TIMELINE_PAGE_SIZE: u32 = 100;
+const THUMBNAIL_SIZES: [u32; 4] = [32, 96, 320, 800];
+const POST_TIMELINE_EVENT_TYPES: [&str; 6] = [
+    "m.room.message",
+    "m.reaction",
+    "m.poll.start",
+    "m.poll.response",
+    "org.matrix.msc3381.poll.start",
+    "org.matrix.msc3381.poll.response",
+];
+
+enum CommentContent {
+    Message(MessageType),
+    Poll {
+        question: String,
+        answers: Vec<(String, String)>,
+        max_selections: usize,
+    },
+    Redacted {
+        reason: Option<String>,
+    },
+}
+
+struct TimelineMessage {
+    event_id: OwnedEventId,
+    sender: OwnedUserId,
+    content: CommentContent,
+    origin_server_ts: u64,
+    in_reply_to: Option<OwnedEventId>,
+    thread_root: Option<OwnedEventId>,
+}
+
+#[derive(Clone)]
+struct UserClient {
+    client: Client,
+    expires_at: u64,
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+struct Edit {
+    sender: OwnedUserId,
+    msgtype: MessageType,
+    origin_server_ts: u64,
+}
+
+#[derive(Default)]
+struct TimelineContents {
+    messages: Vec<TimelineMessage>,
+    edits: HashMap<OwnedEventId, Vec<Edit>>,
+    reactions: HashMap<OwnedEventId, Vec<(String, OwnedUserId, OwnedEventId)>>,
+    poll_responses: HashMap<OwnedEventId, HashMap<OwnedUserId, Vec<String>>>,
+    last_activity_time: u64,
+}
+
+/// Builds the placeholder of a redacted comment. Returns `None` for redacted edits, which leave
+/// the comment they edited as it was.
+fn redacted_message(
+    timeline: &PostTimeline,
+    event_id: OwnedEventId,
+    sender: OwnedUserId,
+    origin_server_ts: u64,
+    reason: Option<String>,
+) -> Option<TimelineMessage> {
+    // Redaction strips the relation from the content, so it is taken from the copy seen before.
+    let (in_reply_to, thread_root) = match timeline.relations.get(&event_id) {
+        Some(EventRelation::Replacement { .. }) => return None,
+        Some(EventRelation::Reply { in_reply_to }) => (Some(in_reply_to.clone()), None),
+        Some(EventRelation::Thread {
+            thread_root,
+            in_reply_to,
+        }) => (in_reply_to.clone(), Some(thread_root.clone())),
+        _ => (None, None),
+    };
+
+    Some(TimelineMessage {
+        event_id,
+        sender,
+        content: CommentContent::Redacted { reason },
+        origin_server_ts,
+        in_reply_to,
+        thread_root,
+    })
+}
+
+/// Sorts the events of a post timeline into comments and the edits, reactions and poll responses
+/// relating to them.
+fn timeline_contents(room_id: &str, timeline: &PostTimeline) -> TimelineContents {
+    use ruma::events::room::message::Relation;
+    use ruma::events::{AnySyncMessageLikeEvent, SyncMessageLikeEvent};
+
+    let mut contents = TimelineContents::default();
+    for message_raw in &timeline.events {
+        let message = match message_raw.deserialize() {
+            Ok(message) => message,
+            Err(err) => {
+                warn!("Skipping malformed event in {}: {}", room_id, err);
+                continue;
+            }
+        };
+
+        match message {
+            AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(
+                SyncMessageLikeEvent::Original(message),
+            )) => {
+                let origin_server_ts = u64::from(message.origin_server_ts.0);
+                contents.last_activity_time = contents.last_activity_time.max(origin_server_ts);
+
+                let (in_reply_to, thread_root) = match &message.content.relates_to {
+                    Some(Relation::Reply { in_reply_to }) => {
+                        (Some(in_reply_to.event_id.clone()), None)
+                    }
+                    Some(Relation::Thread(thread)) => (
+                        thread
+                            .in_reply_to
+                            .as_ref()
+                            .filter(|_| !thread.is_falling_back)
+                            .map(|in_reply_to| in_reply_to.event_id.clone()),
+                        Some(thread.event_id.clone()),
+                    ),
+                    _ => (None, None),
+                };
+
+                match message.content.relates_to {
+                    Some(Relation::Replacement(replacement)) => {
+                        contents
+                            .edits
+                            .entry(replacement.event_id)
+                            .or_default()
+                            .push(Edit {
+                                sender: message.sender,
+                                msgtype: replacement.new_content.msgtype,
+                                origin_server_ts,
+                            });
+                    }
+                    _ => contents.messages.push(TimelineMessage {
+                        event_id: message.event_id,
+                        sender: message.sender,
+                        content: CommentContent::Message(message.content.msgtype),
+                        origin_server_ts,
+                        in_reply_to,
+                        thread_root,
+                    }),
+                }
+            }
+            AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(
+                SyncMessageLikeEvent::Redacted(message),
+            )) => {
+                contents.messages.extend(redacted_message(
+                    timeline,
+                    message.event_id,
+                    message.sender,
+                    u64::from(message.origin_server_ts.0),
+                    message
+                        .unsigned
+                        .redacted_because
+                        .and_then(|redaction| redaction.content.reason),
+                ));
+            }
+            AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::PollStart(
+                SyncMessageLikeEvent::Redacted(poll),
+            )) => {
+                contents.messages.extend(redacted_message(
+                    timeline,
+                    poll.event_id,
+                    poll.sender,
+                    u64::from(poll.origin_server_ts.0),
+                    poll.unsigned
+                        .redacted_because
+                        .and_then(|redaction| redaction.content.reason),
+                ));
+            }
+            AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::PollStart(
+                SyncMessageLikeEvent::Original(poll),
+            )) => {
+                let origin_server_ts = u64::from(poll.origin_server_ts.0);
+                contents.last_activity_time = contents.last_activity_time.max(origin_server_ts);
+
+                let poll_start = poll.content.poll_start;
+                contents.messages.push(TimelineMessage {
+                    event_id: poll.event_id,
+                    sender: poll.sender,
+                    content: CommentContent::Poll {
+                        question: poll_start
+                            .question
+                            .text
+                            .find_plain()
+                            .unwrap_or_default()
+                            .to_owned(),
+                        answers: poll_start
+                            .answers
+                            .iter()
+                            .map(|answer| {
+                                (
+                                    answer.id.clone(),
+                                    answer.text.find_plain().unwrap_or_default().to_owned(),
+                                )
+                            })
+                            .collect(),
+                        max_selections: u64::from(poll_start.max_selections) as usize,
+                    },
+                    origin_server_ts,
+                    in_reply_to: None,
+                    thread_root: None,
+                });
+            }
+            AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::PollResponse(
+                SyncMessageLikeEvent::Original(response),
+            )) => {
+                // Only the latest response of each user counts.
+                contents
+                    .poll_responses
+                    .entry(response.content.relates_to.event_id)
+                    .or_default()
+                    .insert(response.sender, response.content.poll_response.answers);
+            }
+            AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::Reaction(
+                SyncMessageLikeEvent::Original(reaction),
+            )) => {
+                contents
+                    .reactions
+                    .entry(reaction.content.relates_to.event_id)
+                    .or_default()
+                    .push((
+                        reaction.content.relates_to.key,
+                        reaction.sender,
+                        reaction.event_id,
+                    ));
+            }
+            _ => (),
+        }
+    }
+
+    contents
+}
+
+/// Replaces the content of a message with its latest edit, returning the time of that edit and the
+/// revisions of the message from the original on.
+fn apply_edits(
+    msgtype: &mut MessageType,
+    sender: &UserId,
+    origin_server_ts: u64,
+    edits: Vec<Edit>,
+) -> (Option<u64>, Vec<types::Revision>) {
+    // Only the original sender may edit a comment.
+    let mut edits = edits
+        .into_iter()
+        .filter(|edit| edit.sender.as_str() == sender.as_str())
+        .collect::<Vec<_>>();
+    edits.sort_by_key(|edit| edit.origin_server_ts);
+
+    let mut revisions = Vec::new();
+    if !edits.is_empty() {
+        revisions.push(types::Revision {
+            body: remove_plain_reply_fallback(msgtype.body()).to_owned(),
+            origin_server_ts,
+        });
+        revisions.extend(edits.iter().map(|edit| types::Revision {
+            body: remove_plain_reply_fallback(edit.msgtype.body()).to_owned(),
+            origin_server_ts: edit.origin_server_ts,
+        }));
+    }
+
+    let last_edit_time = edits.pop().map(|edit| {
+        *msgtype = edit.msgtype;
+        edit.origin_server_ts
+    });
+
+    (last_edit_time, revisions)
+}
+
+fn aggregate_reactions(
+    annotations: Vec<(String, OwnedUserId, OwnedEventId)>,
+) -> Vec<types::Reaction> {
+    let mut reactions: Vec<types::Reaction> = Vec::new();
+    for (key, sender, event_id) in annotations {
+        let sender = types::ReactionSender {
+            user_id: sender.as_str().to_owned(),
+            event_id: event_id.as_str().to_owned(),
+        };
+
+        match reactions.iter_mut().find(|reaction| reaction.key == key) {
+            Some(reaction) => reaction.senders.push(sender),
+            None => reactions.push(types::Reaction {
+                key,
+                senders: vec![sender],
+            }),
+        }
+    }
+
+    reactions
+}
+
+static HTML_TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>").expect("regex is valid"));
+
+fn html_to_text(html: &str) -> String {
+    html_escape::decode_html_entities(&HTML_TAG_RE.replace_all(html, " ")).into_owned()
+}
+
+fn search_documents(
+    (post, comments): &(types::Post, Vec<types::Comment>),
+    category_room_local_id: Option<String>,
+) -> Vec<SearchDocument> {
+    let mut documents = vec![SearchDocument {
+        room_id: post.room_id.clone(),
+        event_id: None,
+        category_room_local_id: category_room_local_id.clone(),
+        post_title: post.title.clone(),
+        sender: post.author.clone(),
+        sender_name: post.author.clone(),
+        text: post.topic.clone().unwrap_or_default(),
+        origin_server_ts: post.creation_time,
+    }];
+
+    let mut pending = comments.iter().collect::<Vec<_>>();
+    while let Some(comment) = pending.pop() {
+        pending.extend(comment.thread_replies.iter());
+        if comment.redaction.is_some() {
+            continue;
+        }
+
+        let text = match &comment.kind {
+            types::CommentKind::Location { description, .. } => description.clone(),
+            types::CommentKind::Poll(poll) => std::iter::once(poll.question.as_str())
+                .chain(poll.answers.iter().map(|answer| answer.text.as_str()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => html_to_text(&comment.html),
+        };
+
+        documents.push(SearchDocument {
+            room_id: post.room_id.clone(),
+            event_id: Some(comment.event_id.clone()),
+            category_room_local_id: category_room_local_id.clone(),
+            post_title: post.title.clone(),
+            sender: comment.sender.clone(),
+            sender_name: comment.sender_name.clone(),
+            text,
+            origin_server_ts: comment.origin_server_ts,
+        });
+    }
+
+    documents
+}
+
+fn search_snippet(text: &str, terms: &BTreeSet<String>) -> String {
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    let position = words
+        .iter()
+        .position(|word| tokenize(word).iter().any(|term| terms.contains(term)))
+        .unwrap_or_default();
+
+    let start = position.saturating_sub(SEARCH_SNIPPET_CONTEXT);
+    let end = (position + SEARCH_SNIPPET_CONTEXT * 2).min(words.len());
+    let mut snippet = words[start..end].join(" ");
+    if start > 0 {
+        snippet = format!("… {}", snippet);
+    }
+    if end < words.len() {
+        snippet = format!("{} …", snippet);
+    }
+    snippet
+}
+
+fn group_threads(comments: Vec<types::Comment>) -> Vec<types::Comment> {
+    let mut threads: HashMap<String, Vec<types::Comment>> = HashMap::new();
+    let mut toplevel = Vec::new();
+    for comment in comments {
+        match comment.thread_root.clone() {
+            Some(thread_root) => threads.entry(thread_root).or_default().push(comment),
+            None => toplevel.push(comment),
+        }
+    }
+
+    for comment in &mut toplevel {
+        if let Some(replies) = threads.remove(&comment.event_id) {
+            comment.thread_replies = replies;
+        }
+    }
+
+    if !threads.is_empty() {
+        toplevel.extend(threads.into_values().flatten());
+        toplevel.sort_by_key(|comment| comment.origin_server_ts);
+    }
+
+    toplevel
+}
+
+fn timeline_event_id(event: &Raw<AnySyncTimelineEvent>) -> Option<OwnedEventId> {
+    event.get_field("event_id").ok().flatten()
+}
+
+fn timeline_event_type(event: &Raw<AnySyncTimelineEvent>) -> Option<String> {
+    event.get_field("type").ok().flatten()
+}
+
+fn timeline_event_relation(event: &Raw<AnySyncTimelineEvent>) -> Option<EventRelation> {
+    #[derive(Deserialize)]
+    struct InReplyTo {
+        event_id: OwnedEventId,
+    }
+
+    #[derive(Deserialize)]
+    struct RelatesTo {
+        rel_type: Option<String>,
+        event_id: Option<OwnedEventId>,
+        #[serde(rename = "m.in_reply_to")]
+        in_reply_to: Option<InReplyTo>,
+        #[serde(default)]
+        is_falling_back: bool,
+    }
+
+    #[derive(Deserialize)]
+    struct Content {
+        #[serde(rename = "m.relates_to")]
+        relates_to: Option<RelatesTo>,
+    }
+
+    let relates_to = event
+        .get_field::<Content>("content")
+        .ok()
+        .flatten()?
+        .relates_to?;
+    match (relates_to.rel_type.as_deref(), relates_to.event_id) {
+        (Some("m.replace"), Some(event_id)) => Some(EventRelation::Replacement { event_id }),
+        (Some("m.annotation"), Some(event_id)) => Some(EventRelation::Annotation { event_id }),
+        (Some("m.reference"), Some(event_id)) => Some(EventRelation::Reference { event_id }),
+        (Some("m.thread"), Some(thread_root)) => Some(EventRelation::Thread {
+            thread_root,
+            in_reply_to: relates_to
+                .in_reply_to
+                .filter(|_| !relates_to.is_falling_back)
+                .map(|in_reply_to| in_reply_to.event_id),
+        }),
+        (None, _) => relates_to
+            .in_reply_to
+            .map(|in_reply_to| EventRelation::Reply {
+                in_reply_to: in_reply_to.event_id,
+            }),
+        _ => None,
+    }
+}
+
+/// Returns the comment an event belongs to: the comment it edits, reacts to or responds to, or
+/// the event itself.
+fn timeline_event_target(timeline: &PostTimeline, event_id: &EventId) -> OwnedEventId {
+    match timeline.relations.get(event_id) {
+        Some(
+            EventRelation::Replacement { event_id }
+            | EventRelation::Annotation { event_id }
+            | EventRelation::Reference { event_id },
+        ) => event_id.clone(),
+        _ => event_id.to_owned(),
+    }
+}
+
+/// Strips an event the way the homeserver does when serving a redacted event.
+fn redact_timeline_event(
+    event: &Raw<AnySyncTimelineEvent>,
+    redaction: &Raw<AnySyncTimelineEvent>,
+) -> Result<Raw<AnySyncTimelineEvent>, Error> {
+    let mut event: serde_json::Value = event.deserialize_as()?;
+    let redaction: serde_json::Value = redaction.deserialize_as()?;
+
+    if let Some(event) = event.as_object_mut() {
+        event.insert("content".to_owned(), serde_json::json!({}));
+        event.insert(
+            "unsigned".to_owned(),
+            serde_json::json!({ "redacted_because": redaction }),
+        );
+    }
+
+    Ok(Raw::from_json(serde_json::value::to_raw_value(&event)?))
+}
+
+/// Applies the timeline chunk of a sync response to a stored post timeline, returning the IDs of
+/// the new and redacted events. Returns `None` when the chunk is limited and does not overlap with
+/// the stored timeline, as the events in between are then unknown.
+fn merge_timeline(
+    timeline: &mut PostTimeline,
+    events: Vec<Raw<AnySyncTimelineEvent>>,
+    limited: bool,
+) -> Result<Option<Vec<OwnedEventId>>, Error> {
+    let mut known = timeline
+        .events
+        .iter()
+        .filter_map(timeline_event_id)
+        .collect::<HashSet<_>>();
+
+    if limited
+        && !events
+            .iter()
+            .filter_map(timeline_event_id)
+            .any(|event_id| known.contains(&event_id))
+    {
+        return Ok(None);
+    }
+
+    let mut changed = Vec::new();
+    for event in events {
+        let event_id = match timeline_event_id(&event) {
+            Some(event_id) => event_id,
+            None => continue,
+        };
+        if known.contains(&event_id) {
+            continue;
+        }
+
+        match timeline_event_type(&event).as_deref() {
+            Some("m.room.redaction") => {
+                let redacts = match event.get_field::<OwnedEventId>("redacts").ok().flatten() {
+                    Some(redacts) => redacts,
+                    None => continue,
+                };
+                if let Some(redacted) = timeline
+                    .events
+                    .iter_mut()
+                    .find(|event| timeline_event_id(event).as_ref() == Some(&redacts))
+                {
+                    *redacted = redact_timeline_event(redacted, &event)?;
+                    changed.push(redacts);
+                }
+            }
+            Some(event_type) if POST_TIMELINE_EVENT_TYPES.contains(&event_type) => {
+                known.insert(event_id.clone());
+                if let Some(relation) = timeline_event_relation(&event) {
+                    timeline.relations.insert(event_id.clone(), relation);
+                }
+                timeline.events.push(event);
+                changed.push(event_id);
+            }
+            _ => (),
+        }
+    }
+
+    Ok(Some(changed))
+}
+
+fn media_url(mxc: &MxcUri) -> Option<String> {
+    let (server_name, media_id) = mxc.parts().ok()?;
+
+    Some(format!("/media/{}/{}", server_name, media_id))
+}
+
+fn thumbnail_url(mxc: &MxcUri, width: u32, height: u32) -> Option<String> {
+    media_url(mxc).map(|url| format!("{}?width={}&height={}", url, width, height))
+}
+
+fn thumbnail_size(size: u32) -> u32 {
+    THUMBNAIL_SIZES
+        .into_iter()
+        .find(|thumbnail_size| *thumbnail_size >= size)
+        .unwrap_or(THUMBNAIL_SIZES[THUMBNAIL_SIZES.len() - 1])
+}
+
+/// Returns the `(server_name, media_id)` pairs of the media URLs in rendered comment HTML.
+fn media_references(html: &str) -> Vec<(String, String)> {
+    html.split("\"/media/")
+        .skip(1)
+        .filter_map(|rest| {
+            let path = rest.split(|c| c == '"' || c == '?').next()?;
+            let (server_name, media_id) = path.split_once('/')?;
+            Some((server_name.to_owned(), media_id.to_owned()))
+        })
+        .collect()
+}
+
+fn media_source_url(source: &MediaSource) -> Option<String> {
+    match source {
+        MediaSource::Plain(mxc) => media_url(mxc),
+        MediaSource::Encrypted(_) => None,
+    }
+}
+
+static MXC_SRC_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"src="mxc://([^/"]+)/([^"]+)""#).expect("regex is valid"));
+
+fn rewrite_mxc_urls(html: &str) -> String {
+    MXC_SRC_RE
+        .replace_all(html, r#"src="/media/$1/$2""#)
+        .into_owned()
+}
+
+fn markdown_to_html(markdown: &str) -> String {
+    let mut html_body = String::new();
+
+    pulldown_cmark::html::push_html(&mut html_body, pulldown_cmark::Parser::new(markdown));
+    html_body
+}
+
+fn formatted_html(formatted: Option<FormattedBody>, body: &str) -> Option<String> {
+    let mut formatted = formatted.unwrap_or_else(|| FormattedBody::html(markdown_to_html(body)));
+
+    if formatted.format == MessageFormat::Html {
+        formatted.sanitize_html(HtmlSanitizerMode::Strict, RemoveReplyFallback::Yes);
+        Some(rewrite_mxc_urls(&formatted.body))
+    } else {
+        None
+    }
+}
+
+fn geo_uri_map_url(geo_uri: &str) -> Option<String> {
+    let coordinates = geo_uri.strip_prefix("geo:")?.split(';').next()?;
+    let mut coordinates = coordinates.split(',').map(|c| c.parse::<f64>());
+    let latitude = coordinates.next()?.ok()?;
+    let longitude = coordinates.next()?.ok()?;
+
+    Some(format!(
+        "https://www.openstreetmap.org/?mlat={}&mlon={}",
+        latitude, longitude
+    ))
+}
+
+fn message_html(msgtype: MessageType) -> Option<(types::CommentKind, String)> {
+    match msgtype {
+        MessageType::Text(content) => Some((
+            types::CommentKind::Message,
+            formatted_html(content.formatted, &content.body)?,
+        )),
+        MessageType::Notice(content) => Some((
+            types::CommentKind::Notice,
+            formatted_html(content.formatted, &content.body)?,
+        )),
+        MessageType::Emote(content) => Some((
+            types::CommentKind::Emote,
+            formatted_html(content.formatted, &content.body)?,
+        )),
+        MessageType::Location(content) => Some((
+            types::CommentKind::Location {
+                map_url: geo_uri_map_url(&content.geo_uri),
+                geo_uri: content.geo_uri,
+                description: content.body,
+            },
+            String::new(),
+        )),
+        MessageType::Image(content) => {
+            let url = media_source_url(&content.source)?;
+            Some((
+                types::CommentKind::Message,
+                format!(
+                    r#"<a href="{url}"><img class="media" src="{url}?width=800&amp;height=600" alt="{}"></a>"#,
+                    escape_html(&content.body),
+                ),
+            ))
+        }
+        MessageType::Video(content) => {
+            let url = media_source_url(&content.source)?;
+            Some((
+                types::CommentKind::Message,
+                format!(
+                    r#"<video class="media" controls src="{url}">{}</video>"#,
+                    escape_html(&content.body),
+                ),
+            ))
+        }
+        MessageType::Audio(content) => {
+            let url = media_source_url(&content.source)?;
+            Some((
+                types::CommentKind::Message,
+                format!(
+                    r#"<audio controls src="{url}">{}</audio>"#,
+                    escape_html(&content.body),
+                ),
+            ))
+        }
+        MessageType::File(content) => {
+            let url = media_source_url(&content.source)?;
+            Some((
+                types::CommentKind::Message,
+                format!(
+                    r#"<a href="{url}" download>{}</a>"#,
+                    escape_html(&content.body),
+                ),
+            ))
+        }
+        _ => None,
+    }
+}
+
+async fn send_markdown_message(
+    client: &Client,
+    room_id: &RoomId,
+    body: String,
+) -> Result<(), Error> {
+    use ruma::api::client::message::send_message_event;
+    use ruma::events::room::message::RoomMessageEventContent;
+
+    let html = markdown_to_html(&body);
+    let content = RoomMessageEventContent::text_html(body, html);
+    let request =
+        send_message_event::v3::Request::new(room_id.to_owned(), TransactionId::new(), &content)?;
+    client.send(request, None).await?;
+
+    Ok(())
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Message {
+    pub html: String,
+    pub sender: String,
+}
+
+#[derive(Clone)]
+pub struct MatrixService {
+    client: Client,
+    config: Config,
+    index: Index,
+    shutdown: Arc<watch::Sender<bool>>,
+    sync_task: Arc<Mutex<Option<JoinHandle<Result<(), matrix_sdk::Error>>>>>,
+    user_clients: Arc<RwLock<HashMap<(OwnedUserId, OwnedDeviceId), UserClient>>>,
+    post_timeline_locks: Arc<Mutex<HashMap<OwnedRoomId, Arc<Mutex<()>>>>>,
+    media_cache_used: Arc<Mutex<Option<u64>>>,
+    post_events: broadcast::Sender<types::PostEvent>,
+}
+
+impl MatrixService {
+    pub async fn new(config: Config) -> Result<Self, Error> {
+        let client = Client::new(Url::parse(&config.homeserver_url)?).await?;
+
+        let login_res = client
+            .login_username(&config.username, &config.password)
+            .device_id("morum")
+            .initial_device_display_name("Morum")
+            .send()
+            .await?;
+
+        let initial_sync = client
+            .sync_once(SyncSettings::default().full_state(true))
+            .await?;
+
+        info!(
+            "Logged in as {}, got device_id {}",
+            config.username, login_res.device_id,
+        );
+
+        let index = Index::open(&config.index_path)?;
+        let (shutdown, shutdown_receiver) = watch::channel(false);
+
+        let service = Self {
+            client,
+            config,
+            index,
+            shutdown: Arc::new(shutdown),
+            sync_task: Arc::new(Mutex::new(None)),
+            user_clients: Arc::new(RwLock::new(HashMap::new())),
+            post_timeline_locks: Arc::new(Mutex::new(HashMap::new())),
+            media_cache_used: Arc::new(Mutex::new(None)),
+            post_events: broadcast::channel(POST_EVENTS_CAPACITY).0,
+        };
+
+        if let Err(err) = service.index_forum().await {
+            warn!("Failed to index forum: {}", err);
+        }
+        service.index_sync_response(&initial_sync).await;
+        if let Err(err) = service.index_search_documents() {
+            warn!("Failed to index search documents: {}", err);
+        }
+
+        let service_sync = service.clone();
+        let sync_task = task::spawn(async move { service_sync.sync(shutdown_receiver).await });
+        *service.sync_task.lock().await = Some(sync_task);
+
+        Ok(service)
+    }
+
+    async fn sync(&self, mut shutdown: watch::Receiver<bool>) -> Result<(), matrix_sdk::Error> {
+        // Only the initial sync asks for the full state, so later responses only carry the rooms
+        // that changed.
+        let sync = self
+            .client
+            .sync_with_callback(SyncSettings::default(), |response| async move {
+                self.index_sync_response(&response).await;
+                if let Err(err) = self.prune_sessions().await {
+                    warn!("Failed to prune expired sessions: {}", err);
+                }
+
+                LoopCtrl::Continue
+            });
+
+        tokio::select! {
+            res = sync => res?,
+            _ = shutdown.changed() => info!("Stopping sync"),
+        }
+
+        Ok(())
+    }
+
+    /// Sends the comments touched by the given events to the open post pages.
+    fn notify_post_events(
+        &self,
+        room_id: &RoomId,
+        timeline: &PostTimeline,
+        comments: &[types::Comment],
+        event_ids: &[OwnedEventId],
+    ) {
+        let mut notified = HashSet::new();
+        for event_id in event_ids {
+            let target = timeline_event_target(timeline, event_id);
+            let comment = match types::Comment::find(comments, target.as_str()) {
+                Some(comment) => comment,
+                None => continue,
+            };
+
+            // Open pages show thread replies as part of their root, so the whole thread is sent.
+            let comment = comment
+                .thread_root
+                .as_ref()
+                .and_then(|thread_root| {
+                    comments
+                        .iter()
+                        .find(|comment| comment.event_id == *thread_root)
+                })
+                .unwrap_or(comment);
+            if !notified.insert(comment.event_id.clone()) {
+                continue;
+            }
+
+            // Sending only fails when nobody is subscribed.
+            let _ = self.post_events.send(types::PostEvent {
+                room_id: room_id.as_str().to_owned(),
+                comment: comment.clone(),
+            });
+        }
+    }
+
+    pub fn subscribe_post_events(&self) -> broadcast::Receiver<types::PostEvent> {
+        self.post_events.subscribe()
+    }
+
+    async fn index_sync_response(&self, response: &SyncResponse) {
+        for (room_id, room) in &response.rooms.join {
+            if let Err(err) = self.index_room_update(room_id, room).await {
+                warn!("Failed to index room {}: {}", room_id, err);
+            }
+        }
+    }
+
+    async fn index_room_update(&self, room_id: &RoomId, update: &JoinedRoom) -> Result<(), Error> {
+        if update.timeline.events.is_empty() && update.state.events.is_empty() {
+            return Ok(());
+        }
+
+        match self.index.room_kind(room_id.as_str())? {
+            Some(RoomKind::Toplevel) => {
+                self.index_categories().await?;
+            }
+            Some(RoomKind::Category { room_alias }) => {
+                self.index_categories().await?;
+                self.index_category_posts(room_alias).await?;
+            }
+            Some(RoomKind::Post {
+                category_room_alias,
+            }) => {
+                let events = update
+                    .timeline
+                    .events
+                    .iter()
+                    .map(|event| event.event.clone())
+                    .collect();
+                let (post, _) = self
+                    .index_post_timeline(room_id, events, update.timeline.limited)
+                    .await?;
+                self.index_category_post(&category_room_alias, post)?;
+            }
+            None => (),
+        }
+
+        Ok(())
+    }
+
+    /// Refreshes a post after acting on it, without waiting for the sync loop to catch up.
+    async fn refresh_post(&self, room_id: &RoomId) -> Result<(), Error> {
+        let category_room_alias = match self.index.room_kind(room_id.as_str())? {
+            Some(RoomKind::Post {
+                category_room_alias,
+            }) => category_room_alias,
+            _ => return Err(Error::UnknownPost),
+        };
+
+        let room = self
+            .client
+            .get_joined_room(room_id)
+            .ok_or(Error::UnknownPost)?;
+        let (events, _) = self.fetch_timeline_page(&room, None).await?;
+        let (post, _) = self.index_post_timeline(room_id, events, true).await?;
+        self.index_category_post(&category_room_alias, post)?;
+
+        Ok(())
+    }
+
+    async fn index_forum(&self) -> Result<(), Error> {
+        let categories = self.fetch_categories().await?;
+        self.index.set_categories(&categories)?;
+
+        for category in categories {
+            let room_alias = self.config.category_alias(&category.room_local_id);
+            if let Err(err) = self.index_category_posts(room_alias.clone()).await {
+                warn!("Failed to index category {}: {}", room_alias, err);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn index_categories(&self) -> Result<Vec<types::Category>, Error> {
+        let categories = self.fetch_categories().await?;
+        self.index.set_categories(&categories)?;
+
+        for category in &categories {
+            let room_alias = self.config.category_alias(&category.room_local_id);
+            if self.index.category_posts(&room_alias)?.is_none() {
+                if let Err(err) = self.index_category_posts(room_alias.clone()).await {
+                    warn!("Failed to index category {}: {}", room_alias, err);
+                }
+            }
+        }
+
+        Ok(categories)
+    }
+
+    async fn index_category_posts(
+        &self,
+        room_alias: String,
+    ) -> Result<(types::Category, Vec<types::Post>), Error> {
+        let category_posts = self.fetch_category_posts(room_alias.clone()).await?;
+        self.index
+            .set_category_posts(&room_alias, &category_posts)?;
+
+        Ok(category_posts)
+    }
+
+    fn index_category_post(&self, room_alias: &str, post: types::Post) -> Result<(), Error> {
+        if let Some((category, mut posts)) = self.index.category_posts(room_alias)? {
+            match posts
+                .iter_mut()
+                .find(|category_post| category_post.room_id == post.room_id)
+            {
+                Some(category_post) => *category_post = post,
+                None => posts.push(post),
+            }
+            self.index
+                .set_category_posts(room_alias, &(category, posts))?;
+        }
+
+        Ok(())
+    }
+
+    /// Locks the stored timeline of a post, so that concurrent updates from the sync loop and from
+    /// refreshes after user actions do not overwrite each other's events.
+    async fn lock_post_timeline(&self, room_id: &RoomId) -> OwnedMutexGuard<()> {
+        let lock = self
+            .post_timeline_locks
+            .lock()
+            .await
+            .entry(room_id.to_owned())
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+
+    async fn index_post_comments(
+        &self,
+        room_id: String,
+    ) -> Result<(types::Post, Vec<types::Comment>), Error> {
+        let room = self
+            .client
+            .get_joined_room(&RoomId::parse(&room_id)?)
+            .ok_or(Error::UnknownPost)?;
+        let _lock = self.lock_post_timeline(room.room_id()).await;
+        let timeline = self.fetch_post_timeline(&room).await?;
+
+        self.set_post_timeline(&room, &timeline).await
+    }
+
+    async fn index_post_timeline(
+        &self,
+        room_id: &RoomId,
+        events: Vec<Raw<AnySyncTimelineEvent>>,
+        limited: bool,
+    ) -> Result<(types::Post, Vec<types::Comment>), Error> {
+        let room = self
+            .client
+            .get_joined_room(room_id)
+            .ok_or(Error::UnknownPost)?;
+        let _lock = self.lock_post_timeline(room_id).await;
+
+        // Without a stored timeline to compare with, every event of the chunk counts as changed.
+        let mut changed = events
+            .iter()
+            .filter_map(|event| {
+                event
+                    .get_field::<OwnedEventId>("redacts")
+                    .ok()
+                    .flatten()
+                    .or_else(|| timeline_event_id(event))
+            })
+            .collect::<Vec<_>>();
+        let timeline = match self.index.post_timeline(room_id.as_str())? {
+            Some(mut timeline) => match merge_timeline(&mut timeline, events, limited)? {
+                Some(merged) => {
+                    changed = merged;
+                    timeline
+                }
+                None => self.fetch_post_timeline(&room).await?,
+            },
+            None => self.fetch_post_timeline(&room).await?,
+        };
+
+        let post_comments = self.set_post_timeline(&room, &timeline).await?;
+        self.notify_post_events(room_id, &timeline, &post_comments.1, &changed);
+
+        Ok(post_comments)
+    }
+
+    async fn set_post_timeline(
+        &self,
+        room: &room::Joined,
+        timeline: &PostTimeline,
+    ) -> Result<(types::Post, Vec<types::Comment>), Error> {
+        let room_id = room.room_id().as_str().to_owned();
+        let post_comments = self.post_comments_from_timeline(room, timeline).await?;
+        self.index.set_post_timeline(&room_id, timeline)?;
+        self.index.set_post_comments(&room_id, &post_comments)?;
+
+        let mut pending = post_comments.1.iter().collect::<Vec<_>>();
+        while let Some(comment) = pending.pop() {
+            pending.extend(comment.thread_replies.iter());
+            let avatar = comment
+                .sender_avatar_url
+                .as_ref()
+                .map(|url| format!("\"{}\"", url));
+            for (server_name, media_id) in std::iter::once(comment.html.as_str())
+                .chain(avatar.as_deref())
+                .flat_map(media_references)
+            {
+                self.index.add_media(&server_name, &media_id)?;
+            }
+        }
+
+        self.index.set_search_documents(
+            &room_id,
+            &search_documents(&post_comments, self.post_category_local_id(&room_id)?),
+        )?;
+
+        Ok(post_comments)
+    }
+
+    fn post_category_local_id(&self, room_id: &str) -> Result<Option<String>, Error> {
+        Ok(match self.index.room_kind(room_id)? {
+            Some(RoomKind::Post {
+                category_room_alias,
+            }) => self.config.category_local_id(&category_room_alias),
+            _ => None,
+        })
+    }
+
+    /// Rebuilds the search documents of every indexed post, including posts that did not change
+    /// since the index was last written.
+    fn index_search_documents(&self) -> Result<(), Error> {
+        for room_id in self.index.post_room_ids()? {
+            if let Some(post_comments) = self.index.post_comments(&room_id)? {
+                self.index.set_search_documents(
+                    &room_id,
+                    &search_documents(&post_comments, self.post_category_local_id(&room_id)?),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn search(
+        &self,
+        query: &str,
+        category_room_local_id: Option<&str>,
+        author: Option<&str>,
+    ) -> Result<Vec<types::SearchResult>, Error> {
+        let terms = tokenize(query);
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let categories = self.categories().await?;
+        let author = author.map(|author| author.to_lowercase());
+
+        let mut results = self
+            .index
+            .search(&terms)?
+            .into_iter()
+            .filter(|document| {
+                category_room_local_id.map_or(true, |category_room_local_id| {
+                    document.category_room_local_id.as_deref() == Some(category_room_local_id)
+                })
+            })
+            .filter(|document| {
+                author.as_ref().map_or(true, |author| {
+                    document.sender.to_lowercase() == *author
+                        || document.sender_name.to_lowercase().contains(author)
+                })
+            })
+            .map(|document| types::SearchResult {
+                category: categories
+                    .iter()
+                    .find(|category| {
+                        Some(&category.room_local_id) == document.category_room_local_id.as_ref()
+                    })
+                    .cloned(),
+                snippet: search_snippet(&document.text, &terms),
+                room_id: document.room_id,
+                event_id: document.event_id,
+                post_title: document.post_title,
+                sender: document.sender,
+                sender_name: document.sender_name,
+                origin_server_ts: document.origin_server_ts,
+            })
+            .collect::<Vec<_>>();
+        results.sort_by_key(|result| Reverse(result.origin_server_ts));
+
+        Ok(results)
+    }
+
+    pub async fn categories(&self) -> Result<Vec<types::Category>, Error> {
+        match self.index.categories()? {
+            Some(categories) => Ok(categories),
+            None => self.index_categories().await,
+        }
+    }
+
+    pub async fn category_posts(
+        &self,
+        room_alias: String,
+    ) -> Result<(types::Category, Vec<types::Post>), Error> {
+        match self.index.category_posts(&room_alias)? {
+            Some(category_posts) => Ok(category_posts),
+            None => self.index_category_posts(room_alias).await,
+        }
+    }
+
+    pub async fn post_comments(
+        &self,
+        room_id: String,
+    ) -> Result<(types::Post, Vec<types::Comment>), Error> {
+        match self.index.post_comments(&room_id)? {
+            Some(post_comments) => Ok(post_comments),
+            None => self.index_post_comments(room_id).await,
+        }
+    }
+
+    pub async fn shutdown(&self) -> Result<(), Error> {
+        let _ = self.shutdown.send(true);
+
+        let sync_task = self.sync_task.lock().await.take();
+        if let Some(sync_task) = sync_task {
+            sync_task.await??;
+        }
+
+        Ok(())
+    }
+
+    pub async fn login(
+        &self,
+        username: &str,
+        password: &str,
+        expires_at: u64,
+    ) -> Result<(OwnedUserId, OwnedDeviceId), Error> {
+        use ruma::api::client::error::ErrorKind;
+
+        let client = Client::new(Url::parse(&self.config.homeserver_url)?).await?;
+
+        let login_res = client
+            .login_username(username, password)
+            .initial_device_display_name("Morum web")
+            .send()
+            .await
+            .map_err(|err| match err.client_api_error_kind() {
+                Some(ErrorKind::Forbidden) => Error::InvalidLoginCredential,
+                _ => err.into(),
+            })?;
+
+        info!(
+            "User {} logged in, got device_id {}",
+            login_res.user_id, login_res.device_id,
+        );
+
+        self.index.set_session(&UserSession {
+            user_id: login_res.user_id.as_str().to_owned(),
+            device_id: login_res.device_id.as_str().to_owned(),
+            access_token: login_res.access_token,
+            expires_at,
+        })?;
+        self.user_clients.write().await.insert(
+            (login_res.user_id.clone(), login_res.device_id.clone()),
+            UserClient { client, expires_at },
+        );
+
+        Ok((login_res.user_id, login_res.device_id))
+    }
+
+    pub async fn user_client(&self, user_id: &UserId, device_id: &DeviceId) -> Option<Client> {
+        let user_client = self
+            .user_clients
+            .read()
+            .await
+            .get(&(user_id.to_owned(), device_id.to_owned()))
+            .cloned();
+
+        let res = match user_client {
+            Some(user_client) if user_client.expires_at > unix_time() => {
+                return Some(user_client.client)
+            }
+            Some(_) => self.drop_session(user_id, device_id).await.map(|_| None),
+            None => self.restore_session(user_id, device_id).await,
+        };
+
+        res.unwrap_or_else(|err| {
+            warn!(
+                "Failed to look up session of {} on {}: {}",
+                user_id, device_id, err
+            );
+            None
+        })
+    }
+
+    async fn restore_session(
+        &self,
+        user_id: &UserId,
+        device_id: &DeviceId,
+    ) -> Result<Option<Client>, Error> {
+        use ruma::api::client::{account::whoami, error::ErrorKind};
+
+        let session = match self.index.session(user_id.as_str(), device_id.as_str())? {
+            Some(session) => session,
+            None => return Ok(None),
+        };
+        if session.expires_at <= unix_time() {
+            self.drop_session(user_id, device_id).await?;
+            return Ok(None);
+        }
+
+        let client = self.session_client(&session).await?;
+        match client.send(whoami::v3::Request::new(), None).await {
+            Ok(_) => (),
+            Err(err)
+                if matches!(
+                    err.client_api_error_kind(),
+                    Some(ErrorKind::UnknownToken { .. })
+                ) =>
+            {
+                info!("Session of {} on {} is no longer valid", user_id, device_id);
+                self.index
+                    .remove_session(user_id.as_str(), device_id.as_str())?;
+                return Ok(None);
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        self.user_clients.write().await.insert(
+            (user_id.to_owned(), device_id.to_owned()),
+            UserClient {
+                client: client.clone(),
+                expires_at: session.expires_at,
+            },
+        );
+
+        Ok(Some(client))
+    }
+
+    async fn session_client(&self, session: &UserSession) -> Result<Client, Error> {
+        let client = Client::new(Url::parse(&self.config.homeserver_url)?).await?;
+        client
+            .restore_login(matrix_sdk::Session {
+                access_token: session.access_token.clone(),
+                refresh_token: None,
+                user_id: UserId::parse(&session.user_id)?,
+                device_id: OwnedDeviceId::from(session.device_id.as_str()),
+            })
+            .await?;
+
+        Ok(client)
+    }
+
+    /// Forgets a session and logs out the device it created on the homeserver.
+    async fn drop_session(&self, user_id: &UserId, device_id: &DeviceId) -> Result<(), Error> {
+        let user_client = self
+            .user_clients
+            .write()
+            .await
+            .remove(&(user_id.to_owned(), device_id.to_owned()));
+        let session = self.index.session(user_id.as_str(), device_id.as_str())?;
+        self.index
+            .remove_session(user_id.as_str(), device_id.as_str())?;
+
+        let client = match (user_client, session) {
+            (Some(user_client), _) => user_client.client,
+            (None, Some(session)) => self.session_client(&session).await?,
+            (None, None) => return Ok(()),
+        };
+        client.logout().await?;
+
+        Ok(())
+    }
+
+    async fn prune_sessions(&self) -> Result<(), Error> {
+        let now = unix_time();
+        let mut expired = self
+            .user_clients
+            .read()
+            .await
+            .iter()
+            .filter(|(_, user_client)| user_client.expires_at <= now)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for session in self.index.sessions()? {
+            if session.expires_at <= now {
+                expired.push((
+                    UserId::parse(&session.user_id)?,
+                    OwnedDeviceId::from(session.device_id),
+                ));
+            }
+        }
+        expired.sort();
+        expired.dedup();
+
+        for (user_id, device_id) in expired {
+            info!("Session of {} on {} expired", user_id, device_id);
+            if let Err(err) = self.drop_session(&user_id, &device_id).await {
+                warn!(
+                    "Failed to log out expired session of {} on {}: {}",
+                    user_id, device_id, err
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn logout(&self, user_id: &UserId, device_id: &DeviceId) -> Result<(), Error> {
+        self.drop_session(user_id, device_id).await
+    }
+
+    pub async fn send_comment(
+        &self,
+        client: &Client,
+        room_id: String,
+        body: String,
+    ) -> Result<(), Error> {
+        let room_id = RoomId::parse(room_id)?;
+        if !matches!(
+            self.index.room_kind(room_id.as_str())?,
+            Some(RoomKind::Post { .. })
+        ) {
+            return Err(Error::UnknownPost);
+        }
+
+        client.join_room_by_id(&room_id).await?;
+        send_markdown_message(client, &room_id, body).await?;
+
+        self.refresh_post(&room_id).await?;
+
+        Ok(())
+    }
+
+    pub async fn react(
+        &self,
+        client: &Client,
+        room_id: String,
+        event_id: String,
+        key: String,
+    ) -> Result<(), Error> {
+        use ruma::api::client::message::send_message_event;
+        use ruma::events::reaction::{ReactionEventContent, Relation as ReactionRelation};
+
+        let room_id = RoomId::parse(room_id)?;
+        let event_id = EventId::parse(event_id)?;
+        let user_id = client.user_id().ok_or(Error::RequireLogin)?;
+        if !matches!(
+            self.index.room_kind(room_id.as_str())?,
+            Some(RoomKind::Post { .. })
+        ) {
+            return Err(Error::UnknownPost);
+        }
+
+        let (_, comments) = self.post_comments(room_id.as_str().to_owned()).await?;
+        let comment =
+            types::Comment::find(&comments, event_id.as_str()).ok_or(Error::UnknownComment)?;
+        if comment
+            .reactions
+            .iter()
+            .any(|reaction| reaction.key == key && reaction.reacted_by(user_id.as_str()))
+        {
+            return Ok(());
+        }
+
+        client.join_room_by_id(&room_id).await?;
+        let content = ReactionEventContent::new(ReactionRelation::new(event_id, key));
+        let request =
+            send_message_event::v3::Request::new(room_id.clone(), TransactionId::new(), &content)?;
+        client.send(request, None).await?;
 
-        if let SyncStateEvent::Original(event) = event {
-            Some(event.content)
-        } else {
-            None
+        self.refresh_post(&room_id).await?;
+
+        Ok(())
+    }
+
+    pub async fn unreact(
+        &self,
+        client: &Client,
+        room_id: String,
+        event_id: String,
+        key: String,
+    ) -> Result<(), Error> {
+        use ruma::api::client::redact::redact_event;
+
+        let room_id = RoomId::parse(room_id)?;
+        let user_id = client.user_id().ok_or(Error::RequireLogin)?;
+        if !matches!(
+            self.index.room_kind(room_id.as_str())?,
+            Some(RoomKind::Post { .. })
+        ) {
+            return Err(Error::UnknownPost);
         }
-    } else {
-        None
-    })
-}
 
-#[derive(Clone, Debug, Deserialize, Serialize, EventContent)]
-#[ruma_event(type = "org.corepaper.morum.category", kind = State, state_key_type = EmptyStateKey)]
-pub struct MorumCategoryEventContent {
-    #[serde(default, deserialize_with = "ruma::serde::empty_string_as_none")]
-    pub category: Option<String>,
-}
+        let (_, comments) = self.post_comments(room_id.as_str().to_owned()).await?;
+        let comment = types::Comment::find(&comments, &event_id).ok_or(Error::UnknownComment)?;
+        let reaction_event_ids = comment
+            .reactions
+            .iter()
+            .filter(|reaction| reaction.key == key)
+            .flat_map(|reaction| reaction.senders.iter())
+            .filter(|sender| sender.user_id == user_id.as_str())
+            .map(|sender| EventId::parse(&sender.event_id))
+            .collect::<Result<Vec<_>, _>>()?;
 
-#[derive(PartialEq, Eq, Clone, Debug)]
-pub struct Message {
-    pub html: String,
-    pub sender: String,
-}
+        for reaction_event_id in reaction_event_ids {
+            let request = redact_event::v3::Request::new(
+                room_id.clone(),
+                reaction_event_id,
+                TransactionId::new(),
+            );
+            client.send(request, None).await?;
+        }
 
-pub struct MatrixService {
-    client: Client,
-}
+        self.refresh_post(&room_id).await?;
 
-impl MatrixService {
-    pub async fn new(
-        homeserver_url: String,
-        username: String,
-        password: String,
-    ) -> Result<Self, Error> {
-        let client = Client::new(Url::parse(&homeserver_url)?).await?;
+        Ok(())
+    }
 
-        let login_res = client
-            .login_username(&username, &password)
-            .device_id("morum")
-            .initial_device_display_name("Morum")
-            .send()
-            .await?;
+    pub async fn create_post(
+        &self,
+        client: &Client,
+        category_room_alias: String,
+        title: String,
+        topic: Option<String>,
+        body: String,
+    ) -> Result<OwnedRoomId, Error> {
+        use ruma::api::client::room::create_room::v3::{Request as CreateRoomRequest, RoomPreset};
+        use ruma::events::room::history_visibility::{
+            HistoryVisibility, RoomHistoryVisibilityEventContent,
+        };
+        use ruma::events::room::join_rules::{JoinRule, RoomJoinRulesEventContent};
+        use ruma::events::space::parent::SpaceParentEventContent;
+        use ruma::events::InitialStateEvent;
 
-        client
-            .sync_once(SyncSettings::default().full_state(true))
-            .await?;
+        let category_room_id = self
+            .client
+            .resolve_room_alias(&RoomAliasId::parse(&category_room_alias)?)
+            .await?
+            .room_id;
 
-        let client_sync = client.clone();
-        task::spawn(async move {
-            client_sync
-                .sync(SyncSettings::default().full_state(true))
-                .await?;
+        let initial_state = vec![
+            InitialStateEvent::new(RoomHistoryVisibilityEventContent::new(
+                HistoryVisibility::WorldReadable,
+            ))
+            .to_raw_any(),
+            InitialStateEvent::new(RoomJoinRulesEventContent::new(JoinRule::Public)).to_raw_any(),
+            InitialStateEvent {
+                content: assign!(SpaceParentEventContent::new(true), {
+                    via: Some(self.via_servers()?),
+                }),
+                state_key: category_room_id,
+            }
+            .to_raw_any(),
+        ];
 
-            Ok::<(), matrix_sdk::Error>(())
+        let request = assign!(CreateRoomRequest::new(), {
+            name: Some(title),
+            topic,
+            preset: Some(RoomPreset::PublicChat),
+            initial_state,
         });
+        let room_id = client.send(request, None).await?.room_id;
 
-        info!(
-            "Logged in as {}, got device_id {}",
-            username, login_res.device_id,
-        );
+        send_markdown_message(client, &room_id, body).await?;
+
+        self.add_room_to_space(category_room_alias, room_id.as_str().to_owned())
+            .await?;
 
-        Ok(Self { client })
+        Ok(room_id)
     }
 
-    pub async fn categories(&self) -> Result<Vec<types::Category>, Error> {
+    fn via_servers(&self) -> Result<Vec<OwnedServerName>, Error> {
+        self.config
+            .via_servers
+            .iter()
+            .map(ServerName::parse)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::InvalidViaServer)
+    }
+
+    async fn fetch_categories(&self) -> Result<Vec<types::Category>, Error> {
         use ruma::events::space::child::SpaceChildEventContent;
 
         let toplevel_room_id = self
             .client
-            .resolve_room_alias("#forum:corepaper.org".try_into()?)
+            .resolve_room_alias(&RoomAliasId::parse(&self.config.forum_alias)?)
             .await?
             .room_id;
         let toplevel_room = self
             .client
             .get_joined_room(&toplevel_room_id)
             .ok_or(Error::UnknownToplevelRoom)?;
+        self.index
+            .set_room_kind(toplevel_room_id.as_str(), &RoomKind::Toplevel)?;
 
         let child_state_events = toplevel_room
             .get_state_events_static::<SpaceChildEventContent>()
@@ -117,50 +1610,56 @@ impl MatrixService {
 
         let mut categories = Vec::new();
         for child in children {
-            let room = self
-                .client
-                .get_joined_room(&child)
-                .ok_or(Error::UnknownCategoryRoom)?;
-
-            let room_name_state_events = room
-                .get_state_events_static::<RoomNameEventContent>()
-                .await?;
-            let room_topic_state_events = room
-                .get_state_events_static::<RoomTopicEventContent>()
-                .await?;
-
-            let title = deserialize_sync_state_events_to_content(room_name_state_events)?
-                .and_then(|e| e.name)
-                .ok_or(Error::UnknownCategoryTitle)?;
-            let topic = deserialize_sync_state_events_to_content(room_topic_state_events)?
-                .map(|e| e.topic)
-                .ok_or(Error::UnknownCategoryTopic)?;
-
-            let room_alias = room.canonical_alias().ok_or(Error::InvalidCategoryAlias)?;
-            let re = Regex::new(r"^#forum-(.+):corepaper\.org$").expect("regex is valid");
-            let captures = re.captures(room_alias.as_str());
-
-            let room_local_id = if let Some(captures) = captures {
-                captures
-                    .get(1)
-                    .ok_or(Error::InvalidCategoryAlias)?
-                    .as_str()
-                    .to_owned()
-            } else {
-                return Err(Error::InvalidCategoryAlias);
-            };
-
-            categories.push(types::Category {
-                title,
-                topic,
-                room_local_id,
-            });
+            match self.fetch_category(&child).await {
+                Ok(category) => categories.push(category),
+                Err(err) => warn!("Skipping category room {}: {}", child, err),
+            }
         }
 
         Ok(categories)
     }
 
-    pub async fn category_posts(
+    async fn fetch_category(&self, room_id: &RoomId) -> Result<types::Category, Error> {
+        let room = self
+            .client
+            .get_joined_room(room_id)
+            .ok_or(Error::UnknownCategoryRoom)?;
+
+        let room_name_state_events = room
+            .get_state_events_static::<RoomNameEventContent>()
+            .await?;
+        let room_topic_state_events = room
+            .get_state_events_static::<RoomTopicEventContent>()
+            .await?;
+
+        let title = deserialize_sync_state_events_to_content(room_name_state_events)?
+            .and_then(|e| e.name)
+            .ok_or(Error::UnknownCategoryTitle)?;
+        let topic = deserialize_sync_state_events_to_content(room_topic_state_events)?
+            .map(|e| e.topic)
+            .ok_or(Error::UnknownCategoryTopic)?;
+
+        let room_alias = room.canonical_alias().ok_or(Error::InvalidCategoryAlias)?;
+        let room_local_id = self
+            .config
+            .category_local_id(room_alias.as_str())
+            .ok_or(Error::InvalidCategoryAlias)?;
+
+        self.index.set_room_kind(
+            room_id.as_str(),
+            &RoomKind::Category {
+                room_alias: room_alias.as_str().to_owned(),
+            },
+        )?;
+
+        Ok(types::Category {
+            title,
+            topic,
+            room_local_id,
+        })
+    }
+
+    async fn fetch_category_posts(
         &self,
         room_alias: String,
     ) -> Result<(types::Category, Vec<types::Post>), Error> {
@@ -195,18 +1694,10 @@ impl MatrixService {
         let category_room_alias = category_room
             .canonical_alias()
             .ok_or(Error::InvalidCategoryAlias)?;
-        let re = Regex::new(r"^#forum-(.+):corepaper\.org$").expect("regex is valid");
-        let captures = re.captures(category_room_alias.as_str());
-
-        let category_room_local_id = if let Some(captures) = captures {
-            captures
-                .get(1)
-                .ok_or(Error::InvalidCategoryAlias)?
-                .as_str()
-                .to_owned()
-        } else {
-            return Err(Error::InvalidCategoryAlias);
-        };
+        let category_room_local_id = self
+            .config
+            .category_local_id(category_room_alias.as_str())
+            .ok_or(Error::InvalidCategoryAlias)?;
 
         let category = types::Category {
             title: category_title,
@@ -230,50 +1721,86 @@ impl MatrixService {
 
         let mut posts = Vec::new();
         for child in children {
-            let room = self
-                .client
-                .get_joined_room(&child)
-                .ok_or(Error::UnknownCategoryRoom)?;
-
-            let room_name_state_events = room
-                .get_state_events_static::<RoomNameEventContent>()
-                .await?;
-            let room_topic_state_events = room
-                .get_state_events_static::<RoomTopicEventContent>()
-                .await?;
-
-            let title = deserialize_sync_state_events_to_content(room_name_state_events)?
-                .and_then(|e| e.name)
-                .ok_or(Error::UnknownPostTitle)?;
-            let topic =
-                deserialize_sync_state_events_to_content(room_topic_state_events)?.map(|e| e.topic);
+            self.index.set_room_kind(
+                child.as_str(),
+                &RoomKind::Post {
+                    category_room_alias: room_alias.clone(),
+                },
+            )?;
 
-            let room_id = room.room_id();
-
-            posts.push(types::Post {
-                title,
-                topic,
-                room_id: room_id.as_str().to_owned(),
-            });
+            match self.post_comments(child.as_str().to_owned()).await {
+                Ok((post, _)) => posts.push(post),
+                Err(err) => warn!("Skipping post room {}: {}", child, err),
+            }
         }
 
         Ok((category, posts))
     }
 
-    pub async fn post_comments(
+    async fn fetch_timeline_page(
         &self,
-        room_id: String,
-    ) -> Result<(types::Post, Vec<types::Comment>), Error> {
-        use ruma::events::room::message::{
-            sanitize::{HtmlSanitizerMode, RemoveReplyFallback},
-            FormattedBody, MessageFormat, MessageType, Relation,
+        room: &room::Joined,
+        from: Option<&str>,
+    ) -> Result<(Vec<Raw<AnySyncTimelineEvent>>, Option<String>), Error> {
+        let types_filter = POST_TIMELINE_EVENT_TYPES.map(|event_type| event_type.to_owned());
+        let mut messages_options = MessagesOptions::backward();
+        messages_options.from = from;
+        messages_options.limit = TIMELINE_PAGE_SIZE.into();
+        messages_options.filter.types = Some(&types_filter);
+
+        let messages = room.messages(messages_options).await?;
+        let end = if messages.chunk.is_empty() {
+            None
+        } else {
+            messages.end
         };
-        use ruma::events::{AnyMessageLikeEvent, AnyTimelineEvent, MessageLikeEvent};
+        let events = messages
+            .chunk
+            .into_iter()
+            .rev()
+            .map(|event| event.event.cast())
+            .collect();
 
-        let room = self
-            .client
-            .get_joined_room(&RoomId::parse(&room_id)?)
-            .ok_or(Error::UnknownPost)?;
+        Ok((events, end))
+    }
+
+    async fn fetch_post_timeline(&self, room: &room::Joined) -> Result<PostTimeline, Error> {
+        let mut pages = Vec::new();
+        let mut from = None;
+        loop {
+            let (events, end) = self.fetch_timeline_page(room, from.as_deref()).await?;
+            pages.push(events);
+
+            match end {
+                Some(end) => from = Some(end),
+                None => break,
+            }
+        }
+
+        // Events redacted since they were last seen only keep their relations in the index.
+        let mut relations = self
+            .index
+            .post_timeline(room.room_id().as_str())?
+            .map(|timeline| timeline.relations)
+            .unwrap_or_default();
+        let events = pages.into_iter().rev().flatten().collect::<Vec<_>>();
+        for event in &events {
+            if let (Some(event_id), Some(relation)) =
+                (timeline_event_id(event), timeline_event_relation(event))
+            {
+                relations.insert(event_id, relation);
+            }
+        }
+
+        Ok(PostTimeline { events, relations })
+    }
+
+    async fn post_comments_from_timeline(
+        &self,
+        room: &room::Joined,
+        timeline: &PostTimeline,
+    ) -> Result<(types::Post, Vec<types::Comment>), Error> {
+        let room_id = room.room_id().as_str().to_owned();
 
         let room_name_state_events = room
             .get_state_events_static::<RoomNameEventContent>()
@@ -288,77 +1815,292 @@ impl MatrixService {
         let topic =
             deserialize_sync_state_events_to_content(room_topic_state_events)?.map(|e| e.topic);
 
+        let create_event = room
+            .get_state_events_static::<RoomCreateEventContent>()
+            .await?
+            .first()
+            .map(|event| event.deserialize())
+            .transpose()?;
+        let creation_time = create_event
+            .as_ref()
+            .map(|event| u64::from(event.origin_server_ts().0))
+            .unwrap_or_default();
+        let author = create_event
+            .map(|event| event.sender().as_str().to_owned())
+            .unwrap_or_default();
+
+        let TimelineContents {
+            messages,
+            mut edits,
+            mut reactions,
+            mut poll_responses,
+            last_activity_time,
+        } = timeline_contents(&room_id, timeline);
+        let last_activity_time = last_activity_time.max(creation_time);
+
+        let mut members = HashMap::new();
+        let mut quotes = HashMap::new();
+        let mut comments = Vec::new();
+        for message in messages {
+            let TimelineMessage {
+                event_id,
+                sender,
+                mut content,
+                origin_server_ts,
+                in_reply_to,
+                thread_root,
+            } = message;
+
+            // Edits of redacted comments are dropped along with the original content.
+            let message_edits = edits.remove(&event_id).unwrap_or_default();
+            let (last_edit_time, revisions) = match &mut content {
+                CommentContent::Message(msgtype) => {
+                    apply_edits(msgtype, &sender, origin_server_ts, message_edits)
+                }
+                _ => (None, Vec::new()),
+            };
+
+            let (snippet, html, kind, redaction) = match content {
+                CommentContent::Message(msgtype) => {
+                    let snippet: String = remove_plain_reply_fallback(msgtype.body())
+                        .chars()
+                        .take(QUOTE_SNIPPET_LENGTH)
+                        .collect();
+                    let (kind, html) = match message_html(msgtype) {
+                        Some(message) => message,
+                        None => continue,
+                    };
+
+                    (snippet, html, kind, None)
+                }
+                CommentContent::Poll {
+                    question,
+                    answers,
+                    max_selections,
+                } => {
+                    let responses = poll_responses.remove(&event_id).unwrap_or_default();
+                    let mut answers = answers
+                        .into_iter()
+                        .map(|(id, text)| types::PollAnswer { id, text, votes: 0 })
+                        .collect::<Vec<_>>();
+                    for selections in responses.values() {
+                        for selection in selections.iter().take(max_selections.max(1)) {
+                            if let Some(answer) =
+                                answers.iter_mut().find(|answer| answer.id == *selection)
+                            {
+                                answer.votes += 1;
+                            }
+                        }
+                    }
+
+                    (
+                        question.clone(),
+                        String::new(),
+                        types::CommentKind::Poll(types::Poll {
+                            question,
+                            answers,
+                            voters: responses.len(),
+                        }),
+                        None,
+                    )
+                }
+                CommentContent::Redacted { reason } => (
+                    "[deleted]".to_owned(),
+                    String::new(),
+                    types::CommentKind::Message,
+                    Some(types::Redaction { reason }),
+                ),
+            };
+
+            if !members.contains_key(&sender) {
+                let member = room.get_member(&sender).await?;
+                members.insert(sender.clone(), member);
+            }
+            let member = members.get(&sender).and_then(|member| member.as_ref());
+            let sender_name = member
+                .map(|member| member.name().to_owned())
+                .unwrap_or_else(|| sender.as_str().to_owned());
+            let sender_avatar_url = member
+                .and_then(|member| member.avatar_url())
+                .and_then(|mxc| thumbnail_url(mxc, 48, 48));
+
+            quotes.insert(
+                event_id.as_str().to_owned(),
+                types::Quote {
+                    sender_name: sender_name.clone(),
+                    snippet,
+                },
+            );
+
+            comments.push(types::Comment {
+                event_id: event_id.as_str().to_owned(),
+                sender: sender.as_str().to_owned(),
+                sender_name,
+                sender_avatar_url,
+                html,
+                kind,
+                origin_server_ts,
+                last_edit_time,
+                in_reply_to: in_reply_to.map(|event_id| event_id.as_str().to_owned()),
+                reply_quote: None,
+                thread_root: thread_root.map(|event_id| event_id.as_str().to_owned()),
+                thread_replies: Vec::new(),
+                redaction,
+                revisions,
+                reactions: aggregate_reactions(reactions.remove(&event_id).unwrap_or_default()),
+            });
+        }
+
+        for comment in &mut comments {
+            comment.reply_quote = comment
+                .in_reply_to
+                .as_ref()
+                .and_then(|event_id| quotes.get(event_id))
+                .cloned();
+        }
+
+        let comment_count = comments.len();
+        let comments = group_threads(comments);
+
         let post = types::Post {
             title,
             topic,
-            room_id: room_id,
+            room_id,
+            author,
+            creation_time,
+            last_activity_time,
+            comment_count,
         };
 
-        let types_filter = ["m.room.message".to_string()];
-        let mut messages_options = MessagesOptions::backward();
-        messages_options.limit = js_int::UInt::MAX;
-        messages_options.filter.types = Some(&types_filter);
+        Ok((post, comments))
+    }
 
-        let messages_chunk = room.messages(messages_options).await?.chunk;
+    pub async fn media(
+        &self,
+        server_name: String,
+        media_id: String,
+        thumbnail: Option<u32>,
+    ) -> Result<(Option<String>, Vec<u8>), Error> {
+        use ruma::api::client::media::{get_content, get_content_thumbnail};
 
-        let mut messages = Vec::new();
-        for message_raw in messages_chunk {
-            let message = message_raw.event.deserialize()?;
+        let mxc = OwnedMxcUri::from(format!("mxc://{}/{}", server_name, media_id));
+        mxc.parts()?;
 
-            if let AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
-                MessageLikeEvent::Original(message),
-            )) = message
-            {
-                let sender = message.sender;
-                let (event_id, content) = match message.content.relates_to {
-                    Some(Relation::Replacement(replacement)) => (
-                        replacement.event_id,
-                        replacement.new_content.as_ref().clone(),
-                    ),
-                    _ => (message.event_id, message.content),
-                };
+        // Only media used on the forum is proxied.
+        if !self.index.has_media(&server_name, &media_id)? {
+            return Err(Error::UnknownMedia);
+        }
 
-                if let MessageType::Text(msgtype) = content.msgtype {
-                    if let Some((i, _)) = messages
-                        .iter()
-                        .enumerate()
-                        .find(|(_, (other_event_id, _, _))| *other_event_id == event_id)
-                    {
-                        let item = messages.remove(i);
-                        messages.push(item);
-                    } else {
-                        messages.push((event_id, sender, msgtype));
-                    }
-                }
+        let thumbnail = thumbnail.map(thumbnail_size);
+        let cache_name = match thumbnail {
+            Some(size) => format!("{}-{}-{}", server_name, media_id, size),
+            None => format!("{}-{}", server_name, media_id),
+        };
+        let data_path = self.config.media_cache_path.join(&cache_name);
+        let content_type_path = self
+            .config
+            .media_cache_path
+            .join(format!("{}.content-type", cache_name));
+
+        if let Ok(data) = fs::read(&data_path).await {
+            let content_type = fs::read_to_string(&content_type_path).await.ok();
+            return Ok((content_type, data));
+        }
+
+        let (content_type, data) = match thumbnail {
+            Some(size) => {
+                let request = assign!(
+                    get_content_thumbnail::v3::Request::from_url(&mxc, size.into(), size.into())?,
+                    { method: Some(get_content_thumbnail::v3::Method::Scale) }
+                );
+                let response = self.client.send(request, None).await?;
+                (response.content_type, response.file)
+            }
+            None => {
+                let request = get_content::v3::Request::from_url(&mxc)?;
+                let response = self.client.send(request, None).await?;
+                (response.content_type, response.file)
             }
+        };
+
+        fs::create_dir_all(&self.config.media_cache_path).await?;
+        if let Some(content_type) = &content_type {
+            fs::write(&content_type_path, content_type).await?;
         }
+        let tmp_path = self
+            .config
+            .media_cache_path
+            .join(format!("{}.tmp", cache_name));
+        fs::write(&tmp_path, &data).await?;
+        fs::rename(&tmp_path, &data_path).await?;
 
-        let mut comments = Vec::new();
-        for (_, sender, message) in messages {
-            let mut message = message.formatted.unwrap_or_else(|| {
-                let mut html_body = String::new();
+        let added = data.len() as u64 + content_type.as_ref().map_or(0, |c| c.len() as u64);
+        if let Err(err) = self.evict_media_cache(added).await {
+            warn!("Failed to evict media cache: {}", err);
+        }
 
-                pulldown_cmark::html::push_html(
-                    &mut html_body,
-                    pulldown_cmark::Parser::new(&message.body),
-                );
-                FormattedBody::html(html_body)
-            });
+        Ok((content_type, data))
+    }
 
-            if message.format == MessageFormat::Html {
-                message.sanitize_html(HtmlSanitizerMode::Strict, RemoveReplyFallback::Yes);
-                let html = message.body;
+    /// Accounts for newly cached media and, once the cache outgrows the configured size, removes
+    /// the oldest cached media until it fits again. The directory is only scanned on first use
+    /// and when the cache is full.
+    async fn evict_media_cache(&self, added: u64) -> Result<(), Error> {
+        let mut used = self.media_cache_used.lock().await;
+        if let Some(used) = used.as_mut() {
+            *used += added;
+            if *used <= self.config.media_cache_size {
+                return Ok(());
+            }
+        }
 
-                comments.push(types::Comment {
-                    sender: sender.as_str().to_owned(),
-                    html,
-                });
+        // Media is grouped by cache name, so data and its content type are evicted together.
+        let mut cached = HashMap::new();
+        let mut total_size = 0;
+        let mut entries = fs::read_dir(&self.config.media_cache_path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = match entry.file_name().into_string() {
+                Ok(file_name) => file_name,
+                Err(_) => continue,
+            };
+            // Temporary files are still being written by other requests.
+            if file_name.ends_with(".tmp") {
+                continue;
+            }
+            let metadata = entry.metadata().await?;
+            if !metadata.is_file() {
+                continue;
             }
+
+            let modified = metadata.modified()?;
+            let cache_name = file_name
+                .strip_suffix(".content-type")
+                .unwrap_or(&file_name)
+                .to_owned();
+            let (last_modified, size, paths) =
+                cached
+                    .entry(cache_name)
+                    .or_insert((modified, 0, Vec::new()));
+            *last_modified = modified.max(*last_modified);
+            *size += metadata.len();
+            paths.push(entry.path());
+            total_size += metadata.len();
         }
 
-        comments.reverse();
+        let mut cached = cached.into_values().collect::<Vec<_>>();
+        cached.sort_by_key(|(modified, _, _)| *modified);
+        for (_, size, paths) in cached {
+            if total_size <= self.config.media_cache_size {
+                break;
+            }
+            for path in paths {
+                fs::remove_file(&path).await?;
+            }
+            total_size -= size;
+        }
+        *used = Some(total_size);
 
-        Ok((post, comments))
+        Ok(())
     }
 
     pub async fn add_room_to_space(
@@ -389,7 +2131,7 @@ impl MatrixService {
             .send_state_event_for_key(
                 &new_room_id,
                 assign!(SpaceChildEventContent::new(), {
-                    via: Some(vec!["corepaper.org".try_into()?]),
+                    via: Some(self.via_servers()?),
                 }),
             )
             .await?;
@@ -398,17 +2140,304 @@ impl MatrixService {
             .sync_once(SyncSettings::default().full_state(true))
             .await?;
 
+        self.index_category_posts(category_room_alias).await?;
+
         Ok(())
     }
 }
 
 pub async fn start(config: Config) -> Result<MatrixService, Error> {
-    let matrix =
-        MatrixService::new(config.homeserver_url, config.username, config.password).await?;
-
-    matrix
-        .post_comments("!AZvsRzlxPPMqKlMwMB:pacna.org".to_string())
-        .await?;
+    let matrix = MatrixService::new(config).await?;
 
     Ok(matrix)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn raw_event(event: serde_json::Value) -> Raw<AnySyncTimelineEvent> {
+        Raw::from_json(serde_json::value::to_raw_value(&event).unwrap())
+    }
+
+    fn message(
+        event_id: &str,
+        sender: &str,
+        ts: u64,
+        content: serde_json::Value,
+    ) -> serde_json::Value {
+        json!({
+            "type": "m.room.message",
+            "event_id": event_id,
+            "sender": sender,
+            "origin_server_ts": ts,
+            "content": content,
+        })
+    }
+
+    fn edit(event_id: &str, sender: &str, ts: u64, target: &str, body: &str) -> serde_json::Value {
+        message(
+            event_id,
+            sender,
+            ts,
+            json!({
+                "msgtype": "m.text",
+                "body": format!("* {}", body),
+                "m.new_content": { "msgtype": "m.text", "body": body },
+                "m.relates_to": { "rel_type": "m.replace", "event_id": target },
+            }),
+        )
+    }
+
+    fn redaction(event_id: &str, redacts: &str, ts: u64) -> serde_json::Value {
+        json!({
+            "type": "m.room.redaction",
+            "event_id": event_id,
+            "sender": "@alice:example.org",
+            "origin_server_ts": ts,
+            "redacts": redacts,
+            "content": {},
+        })
+    }
+
+    #[test]
+    fn applies_latest_edit_of_sender() {
+        let original = message(
+            "$a",
+            "@alice:example.org",
+            1,
+            json!({ "msgtype": "m.text", "body": "one" }),
+        );
+        let mut timeline = PostTimeline {
+            events: vec![
+                raw_event(original),
+                raw_event(edit("$c", "@alice:example.org", 3, "$a", "three")),
+                raw_event(edit("$b", "@alice:example.org", 2, "$a", "two")),
+                raw_event(edit("$d", "@mallory:example.org", 4, "$a", "evil")),
+            ],
+            relations: HashMap::new(),
+        };
+        let events = vec![
+            raw_event(edit("$e", "@alice:example.org", 5, "$a", "redacted")),
+            raw_event(redaction("$f", "$e", 6)),
+        ];
+        merge_timeline(&mut timeline, events, false).unwrap();
+
+        let mut contents = timeline_contents("!post:example.org", &timeline);
+        assert_eq!(contents.messages.len(), 1);
+        let message = contents.messages.remove(0);
+        assert_eq!(message.event_id.as_str(), "$a");
+
+        let mut msgtype = match message.content {
+            CommentContent::Message(msgtype) => msgtype,
+            _ => panic!("expected a message"),
+        };
+        let edits = contents.edits.remove(&message.event_id).unwrap();
+        let (last_edit_time, revisions) = apply_edits(&mut msgtype, &message.sender, 1, edits);
+
+        assert_eq!(msgtype.body(), "three");
+        assert_eq!(last_edit_time, Some(3));
+        assert_eq!(
+            revisions
+                .iter()
+                .map(|revision| revision.body.as_str())
+                .collect::<Vec<_>>(),
+            vec!["one", "two", "three"]
+        );
+    }
+
+    #[test]
+    fn keeps_redacted_polls_as_placeholders() {
+        let mut timeline = PostTimeline::default();
+        let events = vec![
+            raw_event(json!({
+                "type": "org.matrix.msc3381.poll.start",
+                "event_id": "$poll",
+                "sender": "@alice:example.org",
+                "origin_server_ts": 1,
+                "content": {
+                    "org.matrix.msc3381.poll.start": {
+                        "question": { "org.matrix.msc1767.text": "Lunch?" },
+                        "answers": [
+                            { "id": "yes", "org.matrix.msc1767.text": "Yes" },
+                            { "id": "no", "org.matrix.msc1767.text": "No" },
+                        ],
+                    },
+                    "org.matrix.msc1767.text": "Lunch?",
+                },
+            })),
+            raw_event(redaction("$redaction", "$poll", 2)),
+        ];
+        merge_timeline(&mut timeline, events, false).unwrap();
+
+        let contents = timeline_contents("!post:example.org", &timeline);
+        assert_eq!(contents.messages.len(), 1);
+        assert_eq!(contents.messages[0].event_id.as_str(), "$poll");
+        assert!(matches!(
+            contents.messages[0].content,
+            CommentContent::Redacted { .. }
+        ));
+    }
+
+    #[test]
+    fn keeps_relations_of_redacted_replies() {
+        let mut timeline = PostTimeline::default();
+        let events = vec![
+            raw_event(message(
+                "$root",
+                "@alice:example.org",
+                1,
+                json!({ "msgtype": "m.text", "body": "root" }),
+            )),
+            raw_event(message(
+                "$reply",
+                "@bob:example.org",
+                2,
+                json!({
+                    "msgtype": "m.text",
+                    "body": "reply",
+                    "m.relates_to": { "rel_type": "m.thread", "event_id": "$root" },
+                }),
+            )),
+            raw_event(redaction("$redaction", "$reply", 3)),
+        ];
+        merge_timeline(&mut timeline, events, false).unwrap();
+
+        let contents = timeline_contents("!post:example.org", &timeline);
+        let reply = &contents.messages[1];
+        assert!(matches!(reply.content, CommentContent::Redacted { .. }));
+        assert_eq!(
+            reply.thread_root.as_ref().map(|id| id.as_str()),
+            Some("$root")
+        );
+    }
+
+    fn comment(event_id: &str, ts: u64, thread_root: Option<&str>) -> types::Comment {
+        types::Comment {
+            event_id: event_id.to_owned(),
+            html: String::new(),
+            kind: types::CommentKind::Message,
+            sender: "@alice:example.org".to_owned(),
+            sender_name: "Alice".to_owned(),
+            sender_avatar_url: None,
+            origin_server_ts: ts,
+            last_edit_time: None,
+            in_reply_to: None,
+            reply_quote: None,
+            thread_root: thread_root.map(|thread_root| thread_root.to_owned()),
+            thread_replies: Vec::new(),
+            redaction: None,
+            revisions: Vec::new(),
+            reactions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn groups_thread_replies_under_root() {
+        let comments = group_threads(vec![
+            comment("$root", 1, None),
+            comment("$other", 2, None),
+            comment("$reply1", 3, Some("$root")),
+            comment("$orphan", 4, Some("$missing")),
+            comment("$reply2", 5, Some("$root")),
+        ]);
+
+        assert_eq!(
+            comments
+                .iter()
+                .map(|comment| comment.event_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["$root", "$other", "$orphan"]
+        );
+        assert_eq!(
+            comments[0]
+                .thread_replies
+                .iter()
+                .map(|reply| reply.event_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["$reply1", "$reply2"]
+        );
+    }
+
+    #[test]
+    fn aggregates_reactions_by_key() {
+        let annotation = |key: &str, sender: &str, event_id: &str| {
+            (
+                key.to_owned(),
+                UserId::parse(sender).unwrap(),
+                EventId::parse(event_id).unwrap(),
+            )
+        };
+        let reactions = aggregate_reactions(vec![
+            annotation("👍", "@alice:example.org", "$a"),
+            annotation("🎉", "@bob:example.org", "$b"),
+            annotation("👍", "@bob:example.org", "$c"),
+        ]);
+
+        assert_eq!(reactions.len(), 2);
+        assert_eq!(reactions[0].key, "👍");
+        assert_eq!(reactions[0].count(), 2);
+        assert!(reactions[0].reacted_by("@bob:example.org"));
+        assert_eq!(reactions[0].senders[1].event_id, "$c");
+        assert_eq!(reactions[1].key, "🎉");
+        assert!(!reactions[1].reacted_by("@alice:example.org"));
+    }
+
+    #[test]
+    fn links_geo_uris_to_maps() {
+        assert_eq!(
+            geo_uri_map_url("geo:51.5008,-0.1247;u=35").as_deref(),
+            Some("https://www.openstreetmap.org/?mlat=51.5008&mlon=-0.1247")
+        );
+        assert_eq!(geo_uri_map_url("geo:north,west"), None);
+        assert_eq!(geo_uri_map_url("javascript:alert(1)"), None);
+    }
+
+    #[test]
+    fn snippets_around_first_match() {
+        let text = (1..=60)
+            .map(|i| format!("word{}", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let terms = tokenize("WORD20");
+
+        let snippet = search_snippet(&text, &terms);
+        assert!(snippet.starts_with("… word8 "));
+        assert!(snippet.ends_with(" word43 …"));
+    }
+
+    #[test]
+    fn decodes_entities_in_html_text() {
+        assert_eq!(
+            html_to_text("<p>caf&eacute;&nbsp;&amp;&#x27;s &amp;lt;</p>"),
+            " café\u{a0}&'s &lt; "
+        );
+    }
+
+    #[test]
+    fn snaps_thumbnail_sizes() {
+        assert_eq!(thumbnail_size(1), 32);
+        assert_eq!(thumbnail_size(48), 96);
+        assert_eq!(thumbnail_size(320), 320);
+        assert_eq!(thumbnail_size(600), 800);
+        assert_eq!(thumbnail_size(5000), 800);
+    }
+
+    #[test]
+    fn finds_media_references() {
+        let html = concat!(
+            r#"<a href="/media/example.org/abc"><img class="media" "#,
+            r#"src="/media/example.org/abc?width=800&amp;height=600"></a>"#,
+            r#"<a href="https://example.org/media/other/def">link</a>"#,
+        );
+
+        assert_eq!(
+            media_references(html),
+            vec![
+                ("example.org".to_owned(), "abc".to_owned()),
+                ("example.org".to_owned(), "abc".to_owned()),
+            ]
+        );
+    }
+}
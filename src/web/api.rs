@@ -0,0 +1,61 @@
+use super::{extract, post::PostQuery, post_list::PostListQuery, AppState};
+use crate::Error;
+use axum::Json;
+use morum_base::types;
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct CategoryPosts {
+    pub category: types::Category,
+    pub posts: types::Page<types::Post>,
+}
+
+#[derive(Serialize)]
+pub struct PostComments {
+    pub post: types::Post,
+    pub comments: types::Page<types::Comment>,
+}
+
+#[derive(Serialize)]
+pub struct ApiError {
+    pub code: u16,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+pub async fn categories(
+    context: extract::State<AppState>,
+) -> Result<Json<Vec<types::Category>>, Error> {
+    Ok(Json(context.matrix.categories().await?))
+}
+
+pub async fn category_posts(
+    context: extract::State<AppState>,
+    path: extract::Path<String>,
+    query: extract::Query<PostListQuery>,
+) -> Result<Json<CategoryPosts>, Error> {
+    let room_local_id = path.0;
+
+    let (category, mut posts) = context
+        .matrix
+        .category_posts(context.config.category_alias(&room_local_id))
+        .await?;
+    query.sort.sort(&mut posts);
+    let posts = types::Page::new(posts, query.page, super::post_list::POSTS_PER_PAGE);
+
+    Ok(Json(CategoryPosts { category, posts }))
+}
+
+pub async fn post_comments(
+    context: extract::State<AppState>,
+    path: extract::Path<String>,
+    query: extract::Query<PostQuery>,
+) -> Result<Json<PostComments>, Error> {
+    let room_id = path.0;
+
+    let (post, comments) = context.matrix.post_comments(room_id).await?;
+    let comments = types::Page::new(comments, query.page, super::post::COMMENTS_PER_PAGE);
+
+    Ok(Json(PostComments { post, comments }))
+}
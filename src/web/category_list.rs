@@ -3,15 +3,31 @@ use crate::Error;
 use east::{render, render_with_component};
 use morum_ui::{AnyComponent, App, CategoryList};
 
-pub async fn view_category_list(context: extract::State<AppState>) -> Result<Html, Error> {
+pub async fn view_category_list(
+    context: extract::State<AppState>,
+    session: extract::Session,
+) -> Result<Html, Error> {
     let categories = context.matrix.categories().await?;
 
     Ok(Html {
         header: render! {
             title { "Home | morum" },
+            link {
+                rel: "alternate",
+                type_: "application/atom+xml",
+                title: "morum",
+                href: "/feed.atom",
+            },
+            link {
+                rel: "alternate",
+                type_: "application/rss+xml",
+                title: "morum",
+                href: "/feed.rss",
+            },
         },
         body: render_with_component!(AnyComponent, {
             App {
+                user: session.user_id(),
                 CategoryList {
                     categories: categories,
                 },
@@ -1,10 +1,108 @@
+use super::AppState;
 use crate::Error;
 use async_trait::async_trait;
-use axum::{body::HttpBody, extract::FromRequest, BoxError};
-use http::Request;
-use serde::de::DeserializeOwned;
+use axum::{
+    body::HttpBody,
+    extract::{FromRequest, FromRequestParts},
+    BoxError,
+};
+use axum_extra::extract::CookieJar;
+use http::{request::Parts, Request};
+use jsonwebtoken::{DecodingKey, Validation};
+use matrix_sdk::Client;
+use ruma::{OwnedDeviceId, OwnedUserId, UserId};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::ops::Deref;
 
+pub const SESSION_COOKIE: &str = "morum_session";
+
+#[derive(Serialize, Deserialize)]
+pub struct SessionClaims {
+    pub sub: String,
+    pub device_id: String,
+    pub exp: u64,
+}
+
+#[derive(Clone)]
+pub struct User {
+    pub user_id: OwnedUserId,
+    pub device_id: OwnedDeviceId,
+    pub client: Client,
+}
+
+pub struct Session(pub Option<User>);
+
+impl Session {
+    pub fn user_id(&self) -> Option<String> {
+        self.0.as_ref().map(|user| user.user_id.as_str().to_owned())
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for Session {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let session_secret = match &state.config.session_secret {
+            Some(session_secret) => session_secret,
+            None => return Ok(Session(None)),
+        };
+        let jar = CookieJar::from_request_parts(parts, state).await?;
+        let token = match jar.get(SESSION_COOKIE) {
+            Some(cookie) => cookie.value().to_owned(),
+            None => return Ok(Session(None)),
+        };
+
+        let claims = match jsonwebtoken::decode::<SessionClaims>(
+            &token,
+            &DecodingKey::from_secret(session_secret.as_bytes()),
+            &Validation::default(),
+        ) {
+            Ok(data) => data.claims,
+            Err(_) => return Ok(Session(None)),
+        };
+
+        let user_id = UserId::parse(claims.sub)?;
+        let device_id = OwnedDeviceId::from(claims.device_id);
+        let client = state.matrix.user_client(&user_id, &device_id).await;
+
+        Ok(Session(client.map(|client| User {
+            user_id,
+            device_id,
+            client,
+        })))
+    }
+}
+
+pub struct LoggedIn(pub User);
+
+#[async_trait]
+impl FromRequestParts<AppState> for LoggedIn {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        Session::from_request_parts(parts, state)
+            .await?
+            .0
+            .map(LoggedIn)
+            .ok_or(Error::RequireLogin)
+    }
+}
+
+impl Deref for LoggedIn {
+    type Target = User;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 pub use axum::extract::State;
 
 pub struct Form<T>(pub T);
@@ -33,4 +131,31 @@ impl<T> Deref for Form<T> {
     }
 }
 
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Query(
+            axum::extract::Query::from_request_parts(parts, state)
+                .await?
+                .0,
+        ))
+    }
+}
+
+impl<T> Deref for Query<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 pub use axum::extract::Path;
@@ -0,0 +1,310 @@
+use super::{extract, AppState};
+use crate::Error;
+use axum::response::{IntoResponse, Response};
+use http::header;
+use morum_base::{escape::escape_html, types};
+use std::cmp::Reverse;
+use time::{
+    format_description::well_known::{Rfc2822, Rfc3339},
+    OffsetDateTime,
+};
+
+const FEED_ENTRIES: usize = 50;
+
+pub enum FeedFormat {
+    Atom,
+    Rss,
+}
+
+struct Feed {
+    title: String,
+    subtitle: String,
+    url: String,
+    feed_url: String,
+    entries: Vec<FeedEntry>,
+}
+
+struct FeedEntry {
+    title: String,
+    url: String,
+    author: String,
+    updated: u64,
+    content: String,
+}
+
+fn timestamp(millis: u64) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp((millis / 1000) as i64)
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+}
+
+impl Feed {
+    fn updated(&self) -> u64 {
+        self.entries
+            .iter()
+            .map(|entry| entry.updated)
+            .max()
+            .unwrap_or_default()
+    }
+
+    fn atom(&self) -> Result<String, Error> {
+        let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+        xml.push_str(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+        xml.push_str(&format!(
+            r#"<id>{url}</id><title>{}</title><subtitle>{}</subtitle><updated>{}</updated><link href="{url}"/><link rel="self" href="{}"/>"#,
+            escape_html(&self.title),
+            escape_html(&self.subtitle),
+            timestamp(self.updated()).format(&Rfc3339)?,
+            escape_html(&self.feed_url),
+            url = escape_html(&self.url),
+        ));
+
+        for entry in &self.entries {
+            xml.push_str(&format!(
+                r#"<entry><id>{url}</id><title>{}</title><link href="{url}"/><author><name>{}</name></author><updated>{}</updated><content type="html">{}</content></entry>"#,
+                escape_html(&entry.title),
+                escape_html(&entry.author),
+                timestamp(entry.updated).format(&Rfc3339)?,
+                escape_html(&entry.content),
+                url = escape_html(&entry.url),
+            ));
+        }
+
+        xml.push_str("</feed>");
+        Ok(xml)
+    }
+
+    fn rss(&self) -> Result<String, Error> {
+        let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+        xml.push_str(r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:dc="http://purl.org/dc/elements/1.1/"><channel>"#);
+        xml.push_str(&format!(
+            r#"<title>{}</title><link>{}</link><description>{}</description><lastBuildDate>{}</lastBuildDate><atom:link href="{}" rel="self" type="application/rss+xml"/>"#,
+            escape_html(&self.title),
+            escape_html(&self.url),
+            escape_html(&self.subtitle),
+            timestamp(self.updated()).format(&Rfc2822)?,
+            escape_html(&self.feed_url),
+        ));
+
+        for entry in &self.entries {
+            xml.push_str(&format!(
+                r#"<item><guid isPermaLink="true">{url}</guid><title>{}</title><link>{url}</link><dc:creator>{}</dc:creator><pubDate>{}</pubDate><description>{}</description></item>"#,
+                escape_html(&entry.title),
+                escape_html(&entry.author),
+                timestamp(entry.updated).format(&Rfc2822)?,
+                escape_html(&entry.content),
+                url = escape_html(&entry.url),
+            ));
+        }
+
+        xml.push_str("</channel></rss>");
+        Ok(xml)
+    }
+
+    fn into_response(self, format: FeedFormat) -> Result<Response, Error> {
+        Ok(match format {
+            FeedFormat::Atom => (
+                [(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+                self.atom()?,
+            )
+                .into_response(),
+            FeedFormat::Rss => (
+                [(header::CONTENT_TYPE, "application/rss+xml; charset=utf-8")],
+                self.rss()?,
+            )
+                .into_response(),
+        })
+    }
+}
+
+fn feed_path(path: &str, format: &FeedFormat) -> String {
+    match format {
+        FeedFormat::Atom => format!("{}feed.atom", path),
+        FeedFormat::Rss => format!("{}feed.rss", path),
+    }
+}
+
+fn post_entry(public_url: &str, post: types::Post) -> FeedEntry {
+    FeedEntry {
+        url: format!("{}/post/{}", public_url, post.room_id),
+        content: escape_html(&post.topic.unwrap_or_default()),
+        title: post.title,
+        author: post.author,
+        updated: post.creation_time,
+    }
+}
+
+fn comment_content(comment: &types::Comment) -> String {
+    match &comment.kind {
+        types::CommentKind::Location { description, .. } => escape_html(description),
+        types::CommentKind::Poll(poll) => escape_html(&poll.question),
+        _ => comment.html.clone(),
+    }
+}
+
+async fn forum_feed(
+    context: extract::State<AppState>,
+    format: FeedFormat,
+) -> Result<Response, Error> {
+    let public_url = context.config.public_url.trim_end_matches('/');
+
+    let mut posts = Vec::new();
+    for category in context.matrix.categories().await? {
+        let (_, category_posts) = context
+            .matrix
+            .category_posts(context.config.category_alias(&category.room_local_id))
+            .await?;
+        posts.extend(category_posts);
+    }
+    posts.sort_by_key(|post| Reverse(post.creation_time));
+
+    Feed {
+        title: "morum".to_owned(),
+        subtitle: "New posts".to_owned(),
+        url: format!("{}/", public_url),
+        feed_url: format!("{}{}", public_url, feed_path("/", &format)),
+        entries: posts
+            .into_iter()
+            .take(FEED_ENTRIES)
+            .map(|post| post_entry(public_url, post))
+            .collect(),
+    }
+    .into_response(format)
+}
+
+async fn category_feed(
+    context: extract::State<AppState>,
+    path: extract::Path<String>,
+    format: FeedFormat,
+) -> Result<Response, Error> {
+    let public_url = context.config.public_url.trim_end_matches('/');
+    let room_local_id = path.0;
+
+    let (category, mut posts) = context
+        .matrix
+        .category_posts(context.config.category_alias(&room_local_id))
+        .await?;
+    posts.sort_by_key(|post| Reverse(post.creation_time));
+
+    let url = format!("{}/category/{}", public_url, room_local_id);
+    Feed {
+        title: format!("{} | morum", category.title),
+        subtitle: category.topic,
+        feed_url: format!("{}{}", url, feed_path("/", &format)),
+        url,
+        entries: posts
+            .into_iter()
+            .take(FEED_ENTRIES)
+            .map(|post| post_entry(public_url, post))
+            .collect(),
+    }
+    .into_response(format)
+}
+
+async fn post_feed(
+    context: extract::State<AppState>,
+    path: extract::Path<String>,
+    format: FeedFormat,
+) -> Result<Response, Error> {
+    let public_url = context.config.public_url.trim_end_matches('/');
+    let room_id = path.0;
+
+    let (post, comments) = context.matrix.post_comments(room_id).await?;
+
+    let mut flattened = Vec::new();
+    let mut pending = comments;
+    while let Some(mut comment) = pending.pop() {
+        pending.append(&mut comment.thread_replies);
+        if comment.redaction.is_none() {
+            flattened.push(comment);
+        }
+    }
+    flattened.sort_by_key(|comment| Reverse(comment.origin_server_ts));
+
+    let url = format!("{}/post/{}", public_url, post.room_id);
+    Feed {
+        title: format!("{} | morum", post.title),
+        subtitle: post.topic.unwrap_or_default(),
+        feed_url: format!("{}{}", url, feed_path("/", &format)),
+        entries: flattened
+            .into_iter()
+            .take(FEED_ENTRIES)
+            .map(|comment| FeedEntry {
+                title: format!("Comment by {} on {}", comment.sender_name, post.title),
+                url: format!("{}/comment/{}", url, comment.event_id),
+                content: comment_content(&comment),
+                author: comment.sender_name,
+                updated: comment.last_edit_time.unwrap_or(comment.origin_server_ts),
+            })
+            .collect(),
+        url,
+    }
+    .into_response(format)
+}
+
+pub async fn view_forum_atom(context: extract::State<AppState>) -> Result<Response, Error> {
+    forum_feed(context, FeedFormat::Atom).await
+}
+
+pub async fn view_forum_rss(context: extract::State<AppState>) -> Result<Response, Error> {
+    forum_feed(context, FeedFormat::Rss).await
+}
+
+pub async fn view_category_atom(
+    context: extract::State<AppState>,
+    path: extract::Path<String>,
+) -> Result<Response, Error> {
+    category_feed(context, path, FeedFormat::Atom).await
+}
+
+pub async fn view_category_rss(
+    context: extract::State<AppState>,
+    path: extract::Path<String>,
+) -> Result<Response, Error> {
+    category_feed(context, path, FeedFormat::Rss).await
+}
+
+pub async fn view_post_atom(
+    context: extract::State<AppState>,
+    path: extract::Path<String>,
+) -> Result<Response, Error> {
+    post_feed(context, path, FeedFormat::Atom).await
+}
+
+pub async fn view_post_rss(
+    context: extract::State<AppState>,
+    path: extract::Path<String>,
+) -> Result<Response, Error> {
+    post_feed(context, path, FeedFormat::Rss).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_feed_content() {
+        let feed = Feed {
+            title: "Tom & Jerry".to_owned(),
+            subtitle: "<script>".to_owned(),
+            url: "https://example.org/post/!a?x=1&y=2".to_owned(),
+            feed_url: "https://example.org/post/!a/feed.atom".to_owned(),
+            entries: vec![FeedEntry {
+                title: "\"Quoted\"".to_owned(),
+                url: "https://example.org/post/!a#$1".to_owned(),
+                author: "<Alice>".to_owned(),
+                updated: 0,
+                content: "<p>Hi &amp; bye</p>".to_owned(),
+            }],
+        };
+
+        for xml in [feed.atom().unwrap(), feed.rss().unwrap()] {
+            assert!(xml.contains("Tom &amp; Jerry"));
+            assert!(xml.contains("&lt;script&gt;"));
+            assert!(xml.contains("x=1&amp;y=2"));
+            assert!(xml.contains("&quot;Quoted&quot;"));
+            assert!(xml.contains("&lt;Alice&gt;"));
+            assert!(xml.contains("&lt;p&gt;Hi &amp;amp; bye&lt;/p&gt;"));
+            assert!(!xml.contains("<script>"));
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{config::TlsConfig, Error};
+use hyper::server::accept::Accept;
+use std::{
+    fs::File,
+    future::Future,
+    io::{self, BufReader},
+    net::SocketAddr,
+    os::unix::fs::FileTypeExt,
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    net::{TcpListener, TcpStream, UnixListener, UnixStream},
+    signal::unix::{signal, SignalKind},
+    sync::mpsc,
+    task, time,
+};
+use tokio_rustls::{
+    rustls::{self, Certificate, PrivateKey},
+    server::TlsStream,
+    TlsAcceptor,
+};
+use tracing::warn;
+
+const ACCEPT_BACKOFF_MIN: Duration = Duration::from_millis(5);
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+pub struct UnixAccept {
+    listener: UnixListener,
+    backoff: Duration,
+    sleep: Option<Pin<Box<time::Sleep>>>,
+}
+
+impl UnixAccept {
+    pub fn bind(path: &Path) -> Result<Self, Error> {
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+            Ok(_) => return Err(Error::UnixSocketPathInUse),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+            Err(err) => return Err(err.into()),
+        }
+
+        Ok(Self {
+            listener: UnixListener::bind(path)?,
+            backoff: ACCEPT_BACKOFF_MIN,
+            sleep: None,
+        })
+    }
+}
+
+impl Accept for UnixAccept {
+    type Conn = UnixStream;
+    type Error = io::Error;
+
+    fn poll_accept(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        loop {
+            if let Some(sleep) = self.sleep.as_mut() {
+                ready!(sleep.as_mut().poll(cx));
+                self.sleep = None;
+            }
+
+            // Errors are not passed on, as hyper stops serving on the first one.
+            match ready!(self.listener.poll_accept(cx)) {
+                Ok((stream, _)) => {
+                    self.backoff = ACCEPT_BACKOFF_MIN;
+                    return Poll::Ready(Some(Ok(stream)));
+                }
+                Err(err) => {
+                    warn!("Failed to accept Unix socket connection: {}", err);
+                    let backoff = self.backoff;
+                    self.sleep = Some(Box::pin(time::sleep(backoff)));
+                    self.backoff = (backoff * 2).min(ACCEPT_BACKOFF_MAX);
+                }
+            }
+        }
+    }
+}
+
+pub struct TlsAccept(mpsc::Receiver<TlsStream<TcpStream>>);
+
+impl TlsAccept {
+    pub async fn bind(addr: &SocketAddr, config: &TlsConfig) -> Result<Self, Error> {
+        let acceptor = TlsAcceptor::from(Arc::new(server_config(config)?));
+        let listener = TcpListener::bind(addr).await?;
+        let (sender, receiver) = mpsc::channel(32);
+
+        task::spawn(async move {
+            let shutdown = shutdown_signal();
+            tokio::pin!(shutdown);
+            let mut backoff = ACCEPT_BACKOFF_MIN;
+
+            loop {
+                let stream = tokio::select! {
+                    _ = &mut shutdown => break,
+                    _ = sender.closed() => break,
+                    accepted = listener.accept() => match accepted {
+                        Ok((stream, _)) => {
+                            backoff = ACCEPT_BACKOFF_MIN;
+                            stream
+                        }
+                        Err(err) => {
+                            warn!("Failed to accept TCP connection: {}", err);
+                            time::sleep(backoff).await;
+                            backoff = (backoff * 2).min(ACCEPT_BACKOFF_MAX);
+                            continue;
+                        }
+                    },
+                };
+
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+                task::spawn(async move {
+                    match acceptor.accept(stream).await {
+                        Ok(stream) => {
+                            let _ = sender.send(stream).await;
+                        }
+                        Err(err) => warn!("TLS handshake failed: {}", err),
+                    }
+                });
+            }
+        });
+
+        Ok(Self(receiver))
+    }
+}
+
+impl Accept for TlsAccept {
+    type Conn = TlsStream<TcpStream>;
+    type Error = io::Error;
+
+    fn poll_accept(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        self.0.poll_recv(cx).map(|stream| stream.map(Ok))
+    }
+}
+
+fn server_config(config: &TlsConfig) -> Result<rustls::ServerConfig, Error> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(&config.cert_path)?))?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+
+    let mut keys =
+        rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(&config.key_path)?))?;
+    if keys.is_empty() {
+        keys =
+            rustls_pemfile::rsa_private_keys(&mut BufReader::new(File::open(&config.key_path)?))?;
+    }
+    let key = keys.into_iter().next().ok_or(Error::InvalidTlsKey)?;
+
+    Ok(rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, PrivateKey(key))?)
+}
+
+pub async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("signal handler is installed");
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => (),
+        _ = terminate.recv() => (),
+    }
+}
@@ -0,0 +1,111 @@
+use super::{extract, AppState, Html};
+use crate::Error;
+use axum::response::Redirect;
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use east::{render, render_with_component};
+use jsonwebtoken::{EncodingKey, Header};
+use morum_ui::{AnyComponent, App, Login};
+use serde::Deserialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SESSION_DURATION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+pub async fn view_login(
+    context: extract::State<AppState>,
+    session: extract::Session,
+) -> Result<Html, Error> {
+    if context.config.session_secret.is_none() {
+        return Err(Error::LoginDisabled);
+    }
+    if session.0.is_some() {
+        return Err(Error::AlreadyLoggedIn);
+    }
+
+    Ok(Html {
+        header: render! {
+            title { "Log in | morum" },
+        },
+        body: render_with_component!(AnyComponent, {
+            App {
+                user: None,
+                Login { },
+            },
+        }),
+    })
+}
+
+#[derive(Deserialize)]
+pub struct LoginForm {
+    pub username: String,
+    pub password: String,
+}
+
+pub async fn act_login(
+    context: extract::State<AppState>,
+    session: extract::Session,
+    jar: CookieJar,
+    form: extract::Form<LoginForm>,
+) -> Result<(CookieJar, Redirect), Error> {
+    let session_secret = context
+        .config
+        .session_secret
+        .as_ref()
+        .ok_or(Error::LoginDisabled)?;
+    if session.0.is_some() {
+        return Err(Error::AlreadyLoggedIn);
+    }
+
+    let exp = (SystemTime::now() + SESSION_DURATION)
+        .duration_since(UNIX_EPOCH)?
+        .as_secs();
+    let (user_id, device_id) = context
+        .matrix
+        .login(&form.username, &form.password, exp)
+        .await?;
+
+    let claims = extract::SessionClaims {
+        sub: user_id.as_str().to_owned(),
+        device_id: device_id.as_str().to_owned(),
+        exp,
+    };
+    let token = jsonwebtoken::encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(session_secret.as_bytes()),
+    )?;
+
+    let cookie = Cookie::build(extract::SESSION_COOKIE, token)
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        // Behind a reverse proxy the connection to morum is plain, so the public URL decides.
+        .secure(context.config.public_url.starts_with("https://"))
+        .max_age(
+            SESSION_DURATION
+                .try_into()
+                .expect("session duration is valid"),
+        )
+        .finish();
+
+    Ok((jar.add(cookie), Redirect::to("/")))
+}
+
+pub async fn act_logout(
+    context: extract::State<AppState>,
+    session: extract::LoggedIn,
+    jar: CookieJar,
+) -> Result<(CookieJar, Redirect), Error> {
+    context
+        .matrix
+        .logout(&session.user_id, &session.device_id)
+        .await?;
+
+    Ok((
+        jar.remove(
+            Cookie::build(extract::SESSION_COOKIE, "")
+                .path("/")
+                .finish(),
+        ),
+        Redirect::to("/"),
+    ))
+}
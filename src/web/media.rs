@@ -0,0 +1,45 @@
+use super::{extract, AppState};
+use crate::Error;
+use axum::response::{IntoResponse, Response};
+use http::header;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct MediaQuery {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+pub async fn view_media(
+    context: extract::State<AppState>,
+    path: extract::Path<(String, String)>,
+    query: extract::Query<MediaQuery>,
+) -> Result<Response, Error> {
+    let (server_name, media_id) = path.0;
+    let thumbnail = match (query.width, query.height) {
+        (Some(width), Some(height)) => Some(width.max(height)),
+        _ => None,
+    };
+
+    let (content_type, data) = context
+        .matrix
+        .media(server_name, media_id, thumbnail)
+        .await?;
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                content_type.unwrap_or_else(|| "application/octet-stream".to_owned()),
+            ),
+            (
+                header::CACHE_CONTROL,
+                "public, max-age=604800, immutable".to_owned(),
+            ),
+            (header::CONTENT_SECURITY_POLICY, "sandbox".to_owned()),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_owned()),
+        ],
+        data,
+    )
+        .into_response())
+}
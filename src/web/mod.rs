@@ -1,14 +1,22 @@
+mod api;
 mod category_list;
 mod extract;
+mod feed;
+mod listen;
+mod login;
+mod media;
 mod post;
 mod post_list;
+mod search;
+mod trace;
 mod user_error;
 
 pub use self::user_error::UserError;
 
 use crate::{Config, Error, MatrixService};
 use axum::{middleware, routing, Router};
-use std::{net::SocketAddr, ops::Deref, sync::Arc};
+use std::{ops::Deref, sync::Arc};
+use tracing::info;
 
 pub struct Context {
     pub config: Config,
@@ -26,6 +34,10 @@ impl Deref for AppState {
     }
 }
 
+fn default_page() -> usize {
+    1
+}
+
 east_build::include_trunk_assets! {
     Asset = Asset,
     Html = Html,
@@ -43,25 +55,104 @@ pub async fn start(config: Config, matrix: MatrixService) -> Result<(), Error> {
     app = route_trunk_assets(app);
     app = app
         .route("/", routing::get(self::category_list::view_category_list))
+        .route(
+            "/login",
+            routing::get(self::login::view_login).post(self::login::act_login),
+        )
+        .route("/logout", routing::post(self::login::act_logout))
+        .route("/feed.atom", routing::get(self::feed::view_forum_atom))
+        .route("/feed.rss", routing::get(self::feed::view_forum_rss))
+        .route(
+            "/category/:id/feed.atom",
+            routing::get(self::feed::view_category_atom),
+        )
+        .route(
+            "/category/:id/feed.rss",
+            routing::get(self::feed::view_category_rss),
+        )
+        .route(
+            "/post/:id/feed.atom",
+            routing::get(self::feed::view_post_atom),
+        )
+        .route(
+            "/post/:id/feed.rss",
+            routing::get(self::feed::view_post_rss),
+        )
         .route(
             "/category/:id",
             routing::get(self::post_list::view_post_list).post(self::post_list::act_post_list),
         )
-        .route("/post/:id", routing::get(self::post::view_post));
+        .route(
+            "/post/:id",
+            routing::get(self::post::view_post).post(self::post::act_post),
+        )
+        .route(
+            "/post/:id/events",
+            routing::get(self::post::view_post_events),
+        )
+        .route(
+            "/post/:id/comment/:event_id",
+            routing::get(self::post::view_comment),
+        )
+        .route(
+            "/post/:id/comment/:event_id/history",
+            routing::get(self::post::view_comment_history),
+        )
+        .route(
+            "/post/:id/thread/:event_id",
+            routing::get(self::post::view_thread),
+        )
+        .route("/search", routing::get(self::search::view_search))
+        .route("/api/v1/categories", routing::get(self::api::categories))
+        .route(
+            "/api/v1/categories/:id/posts",
+            routing::get(self::api::category_posts),
+        )
+        .route(
+            "/api/v1/posts/:id/comments",
+            routing::get(self::api::post_comments),
+        )
+        .route("/media/:server/:id", routing::get(self::media::view_media));
 
-    let state = AppState(context);
+    let state = AppState(context.clone());
 
     let app: Router<()> = app
         .layer(middleware::from_fn_with_state(
             state.clone(),
             self::user_error::handle_error,
         ))
+        .layer(middleware::from_fn(self::trace::trace_request))
         .with_state(state);
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], 8080));
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await?;
+    match (&context.config.unix_socket, &context.config.tls) {
+        (Some(_), Some(_)) => return Err(Error::TlsOnUnixSocket),
+        (Some(path), None) => {
+            info!("Listening on unix:{}", path.display());
+            axum::Server::builder(self::listen::UnixAccept::bind(path)?)
+                .serve(app.into_make_service())
+                .with_graceful_shutdown(self::listen::shutdown_signal())
+                .await?;
+        }
+        (None, Some(tls)) => {
+            info!("Listening on https://{}", context.config.listen);
+            axum::Server::builder(
+                self::listen::TlsAccept::bind(&context.config.listen, tls).await?,
+            )
+            .serve(app.into_make_service())
+            .with_graceful_shutdown(self::listen::shutdown_signal())
+            .await?;
+        }
+        (None, None) => {
+            info!("Listening on http://{}", context.config.listen);
+            axum::Server::bind(&context.config.listen)
+                .serve(app.into_make_service())
+                .with_graceful_shutdown(self::listen::shutdown_signal())
+                .await?;
+        }
+    }
+
+    info!("Shutting down");
+    context.matrix.shutdown().await?;
 
     Ok(())
 }
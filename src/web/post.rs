@@ -1,27 +1,268 @@
 use super::{extract, AppState, Html};
 use crate::Error;
+use axum::response::{
+    sse::{Event, KeepAlive, Sse},
+    Redirect,
+};
 use east::{render, render_with_component};
-use morum_ui::{AnyComponent, App, Post};
+use futures_util::{future, Stream, StreamExt};
+use morum_base::types;
+use morum_ui::{AnyComponent, App, Comment, CommentHistory, Post, Thread};
+use serde::Deserialize;
+use similar::{ChangeTag, TextDiff};
+use std::convert::Infallible;
+use tokio_stream::wrappers::BroadcastStream;
+
+pub const COMMENTS_PER_PAGE: usize = 50;
+
+#[derive(Deserialize)]
+pub struct PostQuery {
+    #[serde(default = "super::default_page")]
+    pub page: usize,
+}
 
 pub async fn view_post(
     context: extract::State<AppState>,
+    session: extract::Session,
     path: extract::Path<String>,
+    query: extract::Query<PostQuery>,
 ) -> Result<Html, Error> {
     let room_id = path.0;
 
     let (post, comments) = context.matrix.post_comments(room_id).await?;
+    // Pages are cut from the indexed thread rather than fetched with Matrix pagination tokens.
+    // Reply quotes, edits and thread grouping need the whole timeline, which the index already
+    // holds and keeps up to date from the sync loop.
+    let comments = types::Page::new(comments, query.page, COMMENTS_PER_PAGE);
 
     Ok(Html {
         header: render! {
             title { format!("{} | morum", post.title) },
+            link {
+                rel: "alternate",
+                type_: "application/atom+xml",
+                title: post.title.clone(),
+                href: format!("/post/{}/feed.atom", post.room_id),
+            },
+            link {
+                rel: "alternate",
+                type_: "application/rss+xml",
+                title: post.title.clone(),
+                href: format!("/post/{}/feed.rss", post.room_id),
+            },
         },
         body: render_with_component!(AnyComponent, {
             App {
+                user: session.user_id(),
                 Post {
                     post: post,
                     comments: comments,
+                    user: session.user_id(),
+                },
+            },
+        }),
+    })
+}
+
+pub async fn view_post_events(
+    context: extract::State<AppState>,
+    session: extract::Session,
+    path: extract::Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, Error> {
+    let room_id = path.0;
+    let user = session.user_id();
+
+    let receiver = context.matrix.subscribe_post_events();
+    context.matrix.post_comments(room_id.clone()).await?;
+
+    let stream = BroadcastStream::new(receiver)
+        .filter_map(move |event| {
+            future::ready(match event {
+                Ok(event) if event.room_id == room_id => {
+                    // Comments are rendered here the same way as on the post page.
+                    let rendered = types::RenderedComment {
+                        event_id: event.comment.event_id.clone(),
+                        html: render_with_component!(AnyComponent, {
+                            Comment {
+                                room_id: event.room_id,
+                                comment: event.comment,
+                                user: user.clone(),
+                                expand_thread: false,
+                            }
+                        })
+                        .0,
+                    };
+                    Event::default().json_data(&rendered).ok().map(Ok)
+                }
+                _ => None,
+            })
+        })
+        .take_until(super::listen::shutdown_signal());
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+pub async fn view_comment(
+    context: extract::State<AppState>,
+    path: extract::Path<(String, String)>,
+) -> Result<Redirect, Error> {
+    let (room_id, event_id) = path.0;
+
+    let (_, comments) = context.matrix.post_comments(room_id.clone()).await?;
+    let index = comments
+        .iter()
+        .position(|comment| {
+            comment.event_id == event_id
+                || comment
+                    .thread_replies
+                    .iter()
+                    .any(|reply| reply.event_id == event_id)
+        })
+        .ok_or(Error::UnknownComment)?;
+    let page = index / COMMENTS_PER_PAGE + 1;
+
+    Ok(Redirect::to(&format!(
+        "/post/{}?page={}#{}",
+        room_id, page, event_id
+    )))
+}
+
+pub async fn view_thread(
+    context: extract::State<AppState>,
+    session: extract::Session,
+    path: extract::Path<(String, String)>,
+) -> Result<Html, Error> {
+    let (room_id, event_id) = path.0;
+
+    let (post, comments) = context.matrix.post_comments(room_id).await?;
+    let root = comments
+        .into_iter()
+        .find(|comment| comment.event_id == event_id)
+        .ok_or(Error::UnknownComment)?;
+
+    Ok(Html {
+        header: render! {
+            title { format!("Thread | {} | morum", post.title) },
+        },
+        body: render_with_component!(AnyComponent, {
+            App {
+                user: session.user_id(),
+                Thread {
+                    post: post,
+                    root: root,
+                    user: session.user_id(),
+                },
+            },
+        }),
+    })
+}
+
+pub async fn view_comment_history(
+    context: extract::State<AppState>,
+    session: extract::Session,
+    path: extract::Path<(String, String)>,
+) -> Result<Html, Error> {
+    let (room_id, event_id) = path.0;
+
+    let (post, comments) = context.matrix.post_comments(room_id).await?;
+    let comment = types::Comment::find(&comments, &event_id).ok_or(Error::UnknownComment)?;
+
+    let mut previous = "";
+    let mut revisions = Vec::new();
+    for revision in &comment.revisions {
+        let diff = TextDiff::from_lines(previous, &revision.body)
+            .iter_all_changes()
+            .map(|change| types::DiffLine {
+                tag: match change.tag() {
+                    ChangeTag::Equal => types::DiffTag::Equal,
+                    ChangeTag::Delete => types::DiffTag::Delete,
+                    ChangeTag::Insert => types::DiffTag::Insert,
+                },
+                text: change.value().to_owned(),
+            })
+            .collect();
+        revisions.push((revision.clone(), diff));
+        previous = &revision.body;
+    }
+
+    Ok(Html {
+        header: render! {
+            title { format!("Edit history | {} | morum", post.title) },
+        },
+        body: render_with_component!(AnyComponent, {
+            App {
+                user: session.user_id(),
+                CommentHistory {
+                    post: post,
+                    event_id: event_id,
+                    revisions: revisions,
                 },
             },
         }),
     })
 }
+
+#[derive(Deserialize)]
+#[serde(tag = "action")]
+pub enum PostForm {
+    NewComment { body: String },
+    React { event_id: String, key: String },
+    Unreact { event_id: String, key: String },
+}
+
+pub async fn act_post(
+    context: extract::State<AppState>,
+    user: extract::LoggedIn,
+    path: extract::Path<String>,
+    form: extract::Form<PostForm>,
+) -> Result<Redirect, Error> {
+    let room_id = path.0;
+
+    match form.0 {
+        PostForm::NewComment { body } => {
+            if body.trim().is_empty() {
+                return Err(Error::EmptyComment);
+            }
+
+            context
+                .matrix
+                .send_comment(&user.client, room_id.clone(), body)
+                .await?;
+
+            let (_, comments) = context.matrix.post_comments(room_id.clone()).await?;
+            let last_page = ((comments.len() + COMMENTS_PER_PAGE - 1) / COMMENTS_PER_PAGE).max(1);
+
+            Ok(Redirect::to(&format!(
+                "/post/{}?page={}",
+                room_id, last_page
+            )))
+        }
+        PostForm::React { event_id, key } => {
+            let key = key.trim().to_owned();
+            if key.is_empty() {
+                return Err(Error::EmptyReaction);
+            }
+
+            context
+                .matrix
+                .react(&user.client, room_id.clone(), event_id.clone(), key)
+                .await?;
+
+            Ok(Redirect::to(&format!(
+                "/post/{}/comment/{}",
+                room_id, event_id
+            )))
+        }
+        PostForm::Unreact { event_id, key } => {
+            context
+                .matrix
+                .unreact(&user.client, room_id.clone(), event_id.clone(), key)
+                .await?;
+
+            Ok(Redirect::to(&format!(
+                "/post/{}/comment/{}",
+                room_id, event_id
+            )))
+        }
+    }
+}
@@ -2,29 +2,59 @@ use super::{extract, AppState, Html};
 use crate::Error;
 use axum::response::Redirect;
 use east::{render, render_with_component};
+use morum_base::types;
 use morum_ui::{AnyComponent, App, PostList};
 use serde::Deserialize;
 
+pub const POSTS_PER_PAGE: usize = 20;
+
+#[derive(Deserialize)]
+pub struct PostListQuery {
+    #[serde(default = "super::default_page")]
+    pub page: usize,
+    #[serde(default)]
+    pub sort: types::PostSort,
+}
+
 pub async fn view_post_list(
     context: extract::State<AppState>,
+    session: extract::Session,
     path: extract::Path<String>,
+    query: extract::Query<PostListQuery>,
 ) -> Result<Html, Error> {
     let room_local_id = path.0;
 
-    let (category, posts) = context
+    let (category, mut posts) = context
         .matrix
-        .category_posts(format!("#forum-{}:corepaper.org", room_local_id))
+        .category_posts(context.config.category_alias(&room_local_id))
         .await?;
+    query.sort.sort(&mut posts);
+    let posts = types::Page::new(posts, query.page, POSTS_PER_PAGE);
 
     Ok(Html {
         header: render! {
             title { format!("{} | morum", category.title) },
+            link {
+                rel: "alternate",
+                type_: "application/atom+xml",
+                title: category.title.clone(),
+                href: format!("/category/{}/feed.atom", room_local_id),
+            },
+            link {
+                rel: "alternate",
+                type_: "application/rss+xml",
+                title: category.title.clone(),
+                href: format!("/category/{}/feed.rss", room_local_id),
+            },
         },
         body: render_with_component!(AnyComponent, {
             App {
+                user: session.user_id(),
                 PostList {
                     category: category,
                     posts: posts,
+                    sort: query.sort,
+                    logged_in: session.0.is_some(),
                 },
             },
         }),
@@ -34,22 +64,57 @@ pub async fn view_post_list(
 #[derive(Deserialize)]
 #[serde(tag = "action")]
 pub enum PostListForm {
-    NewPost { room_id: String },
+    NewPost {
+        room_id: String,
+    },
+    NewTopic {
+        title: String,
+        topic: String,
+        body: String,
+    },
 }
 
 pub async fn act_post_list(
     context: extract::State<AppState>,
+    session: extract::Session,
     path: extract::Path<String>,
     form: extract::Form<PostListForm>,
 ) -> Result<Redirect, Error> {
     let category_room_local_id = path.0;
 
     match form.0 {
+        PostListForm::NewTopic { title, topic, body } => {
+            let user = session.0.ok_or(Error::RequireLogin)?;
+            if title.trim().is_empty() {
+                return Err(Error::EmptyPostTitle);
+            }
+            if body.trim().is_empty() {
+                return Err(Error::EmptyComment);
+            }
+            let topic = if topic.trim().is_empty() {
+                None
+            } else {
+                Some(topic)
+            };
+
+            let room_id = context
+                .matrix
+                .create_post(
+                    &user.client,
+                    context.config.category_alias(&category_room_local_id),
+                    title,
+                    topic,
+                    body,
+                )
+                .await?;
+
+            Ok(Redirect::to(&format!("/post/{}", room_id)))
+        }
         PostListForm::NewPost { room_id } => {
             context
                 .matrix
                 .add_room_to_space(
-                    format!("#forum-{}:corepaper.org", category_room_local_id),
+                    context.config.category_alias(&category_room_local_id),
                     room_id,
                 )
                 .await?;
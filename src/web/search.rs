@@ -0,0 +1,63 @@
+use super::{extract, AppState, Html};
+use crate::Error;
+use east::{render, render_with_component};
+use morum_base::types;
+use morum_ui::{AnyComponent, App, Search};
+use serde::Deserialize;
+use url::form_urlencoded;
+
+const SEARCH_RESULTS_PER_PAGE: usize = 20;
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    pub q: String,
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default = "super::default_page")]
+    pub page: usize,
+}
+
+pub async fn view_search(
+    context: extract::State<AppState>,
+    session: extract::Session,
+    query: extract::Query<SearchQuery>,
+) -> Result<Html, Error> {
+    let query = query.0;
+    let category = Some(query.category.trim()).filter(|category| !category.is_empty());
+    let author = Some(query.author.trim()).filter(|author| !author.is_empty());
+
+    let categories = context.matrix.categories().await?;
+    let results = context.matrix.search(&query.q, category, author).await?;
+    let results = types::Page::new(results, query.page, SEARCH_RESULTS_PER_PAGE);
+
+    let base_url = format!(
+        "/search?{}",
+        form_urlencoded::Serializer::new(String::new())
+            .append_pair("q", &query.q)
+            .append_pair("category", &query.category)
+            .append_pair("author", &query.author)
+            .finish()
+    );
+
+    Ok(Html {
+        header: render! {
+            title { "Search | morum" },
+        },
+        body: render_with_component!(AnyComponent, {
+            App {
+                user: session.user_id(),
+                Search {
+                    query: query.q,
+                    category: query.category,
+                    author: query.author,
+                    categories: categories,
+                    results: results,
+                    base_url: base_url,
+                },
+            },
+        }),
+    })
+}
@@ -0,0 +1,49 @@
+use axum::{
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use std::{fmt, time::Instant};
+use tracing::{info, info_span, Instrument};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+#[derive(Clone, Copy, Debug)]
+pub struct RequestId(Uuid);
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+pub async fn trace_request<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    let request_id = RequestId(Uuid::new_v4());
+    req.extensions_mut().insert(request_id);
+
+    let span = info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        uri = %req.uri(),
+    );
+
+    async move {
+        let start = Instant::now();
+        let mut res = next.run(req).await;
+
+        info!(
+            status = res.status().as_u16(),
+            latency_ms = start.elapsed().as_millis() as u64,
+            "Finished request"
+        );
+
+        if let Ok(value) = HeaderValue::from_str(&request_id.to_string()) {
+            res.headers_mut().insert(REQUEST_ID_HEADER, value);
+        }
+        res
+    }
+    .instrument(span)
+    .await
+}
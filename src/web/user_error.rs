@@ -1,17 +1,33 @@
-use super::Html;
+use super::{api::ApiError, extract, trace::RequestId, AppState, Html};
 use crate::Error;
 use axum::{
+    extract::{FromRequestParts, State},
     http::StatusCode,
     middleware::Next,
     response::{IntoResponse, Redirect, Response},
+    Json,
 };
 use east::{render, render_with_component};
 use http::Request;
 use morum_ui::{AnyComponent, App};
+use ruma::api::client::error::ErrorKind;
 use thiserror::Error;
+use tracing::{error, info};
 
 #[derive(Error, Debug)]
 pub enum UserError {
+    #[error("{0}")]
+    NotFound(&'static str),
+    #[error("{0}")]
+    BadRequest(&'static str),
+    #[error("{0}")]
+    Unauthorized(&'static str),
+    #[error("{0}")]
+    Forbidden(&'static str),
+    #[error("Please log in to continue")]
+    RequireLogin,
+    #[error("The Matrix homeserver is unavailable, please try again later")]
+    ServiceUnavailable,
     #[error("Internal error")]
     Internal,
 }
@@ -19,20 +35,76 @@ pub enum UserError {
 impl UserError {
     pub fn status_code(&self) -> Option<StatusCode> {
         match self {
+            Self::NotFound(_) => Some(StatusCode::NOT_FOUND),
+            Self::BadRequest(_) => Some(StatusCode::BAD_REQUEST),
+            Self::Unauthorized(_) => Some(StatusCode::UNAUTHORIZED),
+            Self::Forbidden(_) => Some(StatusCode::FORBIDDEN),
+            Self::RequireLogin => Some(StatusCode::UNAUTHORIZED),
+            Self::ServiceUnavailable => Some(StatusCode::SERVICE_UNAVAILABLE),
             Self::Internal => Some(StatusCode::INTERNAL_SERVER_ERROR),
         }
     }
 
     pub fn redirect(&self) -> Option<Redirect> {
         match self {
+            Self::RequireLogin => Some(Redirect::to("/login")),
             _ => None,
         }
     }
 }
 
+fn matrix_user_error(kind: Option<&ErrorKind>) -> UserError {
+    match kind {
+        Some(ErrorKind::Forbidden) => {
+            UserError::Forbidden("The Matrix homeserver does not allow this")
+        }
+        Some(ErrorKind::NotFound) => {
+            UserError::NotFound("The Matrix homeserver could not find what was requested")
+        }
+        _ => UserError::ServiceUnavailable,
+    }
+}
+
 impl From<Error> for UserError {
     fn from(err: Error) -> Self {
+        let user_error = Self::from_error(&err);
+        match user_error {
+            Self::ServiceUnavailable | Self::Internal => {
+                error!(error = %err.chain(), "Request failed")
+            }
+            _ => info!(error = %err.chain(), "Request rejected"),
+        }
+        user_error
+    }
+}
+
+impl UserError {
+    fn from_error(err: &Error) -> Self {
         match err {
+            Error::UnknownCategory | Error::UnknownCategoryRoom | Error::InvalidCategoryAlias => {
+                Self::NotFound("This category does not exist")
+            }
+            Error::UnknownPost => Self::NotFound("This post does not exist"),
+            Error::UnknownComment => Self::NotFound("This comment does not exist"),
+            Error::UnknownMedia => Self::NotFound("This media is not available"),
+            Error::MatrixIdParse(_) => {
+                Self::BadRequest("The address contains an invalid Matrix ID")
+            }
+            Error::AxumForm(_) => Self::BadRequest("The submitted form is incomplete or invalid"),
+            Error::AxumQuery(_) => Self::BadRequest("The address contains invalid parameters"),
+            Error::LoginDisabled => Self::Forbidden("Logging in is not enabled on this forum"),
+            Error::AlreadyLoggedIn => Self::BadRequest("You are already logged in"),
+            Error::EmptyComment => Self::BadRequest("Please write something before posting"),
+            Error::EmptyReaction => Self::BadRequest("Please choose a reaction"),
+            Error::EmptyPostTitle => Self::BadRequest("Please give the topic a title"),
+            Error::RequireLogin => Self::RequireLogin,
+            Error::InvalidLoginCredential => {
+                Self::Unauthorized("The username or password is incorrect")
+            }
+            Error::Matrix(matrix_sdk::Error::Http(http_err)) | Error::MatrixHttp(http_err) => {
+                matrix_user_error(http_err.client_api_error_kind())
+            }
+            Error::RumaClient(_) | Error::UnknownToplevelRoom => Self::ServiceUnavailable,
             _ => Self::Internal,
         }
     }
@@ -53,7 +125,11 @@ impl IntoResponse for Error {
     }
 }
 
-pub async fn handle_error<B>(req: Request<B>, next: Next<B>) -> Response {
+pub async fn handle_error<B>(
+    State(state): State<AppState>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
     let host = req.headers().get("Host");
     let referer = req.headers().get("Referer");
     let go_back = host
@@ -72,25 +148,61 @@ pub async fn handle_error<B>(req: Request<B>, next: Next<B>) -> Response {
             }
         });
 
+    let is_api = req.uri().path().starts_with("/api/");
+    let request_id = req.extensions().get::<RequestId>().copied();
+    let headers = req.headers().clone();
+
     let res = next.run(req).await;
 
     if let Some(error) = res.extensions().get::<UserError>() {
+        if is_api {
+            let status_code = error
+                .status_code()
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            let body = Json(ApiError {
+                code: status_code.as_u16(),
+                message: error.to_string(),
+                request_id: request_id.map(|request_id| request_id.to_string()),
+            });
+            return (status_code, body).into_response();
+        }
+
         if let Some(redirect) = error.redirect() {
             return redirect.into_response();
         }
 
+        // The session is only looked up for error pages, from the headers of the request.
+        let (mut parts, _) = Request::new(()).into_parts();
+        parts.headers = headers;
+        let user = extract::Session::from_request_parts(&mut parts, &state)
+            .await
+            .ok()
+            .and_then(|session| session.user_id());
+
         let html = Html {
             header: render! {
                 title { "Error | morum" },
             },
             body: render_with_component!(AnyComponent, {
                 App {
+                    user: user,
                     p {
                         class: "error",
-                        format!("{:?}", error),
+                        error.to_string(),
 
                         br { },
 
+                        request_id.map(|request_id| {
+                            render! {
+                                small {
+                                    class: "request-id",
+                                    "Request ID: ",
+                                    request_id.to_string(),
+                                },
+                                br { },
+                            }
+                        }),
+
                         go_back.map(|go_back| {
                             render! {
                                 a {
@@ -0,0 +1,45 @@
+use crate::AnyComponent;
+use east::{render_from_dyn, rsx, use_state, Element, RenderDyn, Scope};
+use serde::{Deserialize, Serialize};
+
+pub const LONG_COMMENT_LENGTH: usize = 2000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CollapsibleComment {
+    pub html: String,
+}
+
+#[render_from_dyn]
+impl RenderDyn for CollapsibleComment {
+    fn render_dyn(cx: Scope<Self>) -> Element {
+        let expanded = use_state(&cx, || false);
+        let hydrated = use_state(&cx, || false);
+
+        #[cfg(target_arch = "wasm32")]
+        cx.use_hook(|_| hydrated.set(true));
+
+        // Comments are only collapsed once the button to expand them works.
+        let (class, button_class, label) = match (*hydrated.get(), *expanded.get()) {
+            (false, _) => ("card-text collapsible", "btn btn-link d-none", "Show more"),
+            (true, true) => ("card-text collapsible", "btn btn-link", "Show less"),
+            (true, false) => (
+                "card-text collapsible collapsed",
+                "btn btn-link",
+                "Show more",
+            ),
+        };
+
+        cx.render(rsx! {
+            div {
+                class: "{class}",
+                dangerous_inner_html: "{cx.props.html}",
+            },
+            button {
+                r#type: "button",
+                class: "{button_class}",
+                onclick: move |_| expanded.set(!expanded.get()),
+                "{label}",
+            },
+        })
+    }
+}
@@ -0,0 +1,84 @@
+use crate::AnyComponent;
+use east::{render_from_dyn, rsx, use_state, Element, RenderDyn, Scope};
+use pulldown_cmark::{html, Event, Parser};
+use serde::{Deserialize, Serialize};
+
+pub fn markdown_preview(markdown: &str) -> String {
+    // Raw HTML is shown as text. The server instead keeps the tags its sanitizer allows, so the
+    // preview of such comments differs from what gets posted.
+    let parser = Parser::new(markdown).map(|event| match event {
+        Event::Html(html) => Event::Text(html),
+        event => event,
+    });
+
+    let mut preview = String::new();
+    html::push_html(&mut preview, parser);
+    preview
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Composer {
+    pub id: String,
+    pub label: String,
+    pub rows: u32,
+}
+
+#[render_from_dyn]
+impl RenderDyn for Composer {
+    fn render_dyn(cx: Scope<Self>) -> Element {
+        let body = use_state(&cx, String::new);
+        let preview = use_state(&cx, || false);
+
+        let preview_html = markdown_preview(body.get());
+        let (write_class, preview_class) = if *preview.get() {
+            ("nav-link", "nav-link active")
+        } else {
+            ("nav-link active", "nav-link")
+        };
+        let (textarea_class, preview_pane_class) = if *preview.get() {
+            ("form-control d-none", "composer-preview card-text")
+        } else {
+            ("form-control", "composer-preview card-text d-none")
+        };
+
+        cx.render(rsx! {
+            div {
+                class: "form-group composer",
+                label { r#for: "{cx.props.id}", "{cx.props.label}" },
+                ul {
+                    class: "nav nav-tabs",
+                    li {
+                        class: "nav-item",
+                        button {
+                            r#type: "button",
+                            class: "btn btn-link {write_class}",
+                            onclick: move |_| preview.set(false),
+                            "Write",
+                        },
+                    },
+                    li {
+                        class: "nav-item",
+                        button {
+                            r#type: "button",
+                            class: "btn btn-link {preview_class}",
+                            onclick: move |_| preview.set(true),
+                            "Preview",
+                        },
+                    },
+                },
+                textarea {
+                    class: "{textarea_class}",
+                    id: "{cx.props.id}",
+                    name: "body",
+                    rows: "{cx.props.rows}",
+                    value: "{body}",
+                    oninput: move |event| body.set(event.value.clone()),
+                },
+                div {
+                    class: "{preview_pane_class}",
+                    dangerous_inner_html: "{preview_html}",
+                },
+            }
+        })
+    }
+}
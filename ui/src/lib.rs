@@ -1,24 +1,62 @@
 mod category_list;
+mod collapsible;
+mod composer;
+mod live;
+mod login;
+mod new_post_dialog;
+mod pagination;
 mod post;
 mod post_list;
+mod search;
 
 pub use crate::category_list::CategoryList;
-pub use crate::post::Post;
+pub use crate::collapsible::CollapsibleComment;
+pub use crate::composer::Composer;
+pub use crate::live::LiveComments;
+pub use crate::login::Login;
+pub use crate::new_post_dialog::NewPostDialog;
+pub use crate::pagination::Pagination;
+pub use crate::post::{Comment, CommentHistory, Post, Thread};
 pub use crate::post_list::PostList;
+pub use crate::search::Search;
 
-use east::{render_from_multi, render_with_component, HydrateTo, Markup, Render, RenderMulti};
+use east::{
+    render, render_from_multi, render_with_component, HydrateTo, Markup, Render, RenderMulti,
+};
 use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+pub fn format_timestamp(millis: u64) -> String {
+    match OffsetDateTime::from_unix_timestamp((millis / 1000) as i64) {
+        Ok(time) => format!(
+            "{}-{:02}-{:02} {:02}:{:02} UTC",
+            time.year(),
+            u8::from(time.month()),
+            time.day(),
+            time.hour(),
+            time.minute(),
+        ),
+        Err(_) => String::new(),
+    }
+}
 
 #[derive(Serialize, Deserialize, HydrateTo, Debug, Clone)]
-pub enum AnyComponent {}
+pub enum AnyComponent {
+    CollapsibleComment(CollapsibleComment),
+    Composer(Composer),
+    LiveComments(LiveComments),
+    NewPostDialog(NewPostDialog),
+}
 
-pub struct App {}
+pub struct App {
+    pub user: Option<String>,
+}
 
 #[render_from_multi]
 impl RenderMulti<AnyComponent> for App {
     fn render_multi(self, children: Markup) -> Markup {
         render_with_component!(AnyComponent, {
-            Nav { },
+            Nav { user: self.user },
             div {
                 class: "container m-3",
                 children,
@@ -28,10 +66,39 @@ impl RenderMulti<AnyComponent> for App {
     }
 }
 
-pub struct Nav {}
+pub struct Nav {
+    pub user: Option<String>,
+}
 
 impl Render<AnyComponent> for Nav {
     fn render(self) -> Markup {
+        let account = match self.user {
+            Some(user) => render! {
+                li {
+                    class: "nav-item navbar-text",
+                    user,
+                },
+                li {
+                    class: "nav-item",
+                    form {
+                        method: "post",
+                        action: "/logout",
+                        input {
+                            class: "btn btn-link nav-link",
+                            type_: "submit",
+                            value: "Log out",
+                        },
+                    },
+                },
+            },
+            None => render! {
+                li {
+                    class: "nav-item",
+                    a { class: "nav-link", href: "/login", "Log in" },
+                },
+            },
+        };
+
         render_with_component!(AnyComponent, {
             nav {
                 class: "navbar navbar-light navbar-expand-sm",
@@ -39,7 +106,14 @@ impl Render<AnyComponent> for Nav {
                     class: "navbar-brand",
                     a { href: "/", "morum" }
                 },
-                ul { class: "navbar-bav" },
+                ul {
+                    class: "navbar-nav ml-auto",
+                    li {
+                        class: "nav-item",
+                        a { class: "nav-link", href: "/search", "Search" },
+                    },
+                    account,
+                },
             }
         })
     }
@@ -0,0 +1,65 @@
+use crate::AnyComponent;
+use east::{render_from_dyn, rsx, Element, RenderDyn, Scope};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LiveComments {
+    pub room_id: String,
+    pub append: bool,
+}
+
+#[render_from_dyn]
+impl RenderDyn for LiveComments {
+    fn render_dyn(cx: Scope<Self>) -> Element {
+        #[cfg(target_arch = "wasm32")]
+        cx.use_hook(|_| subscribe(&cx.props.room_id, cx.props.append));
+
+        cx.render(rsx! {
+            div { class: "live-comments" }
+        })
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn subscribe(room_id: &str, append: bool) -> web_sys::EventSource {
+    use morum_base::types;
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    let source = web_sys::EventSource::new(&format!("/post/{}/events", room_id))
+        .expect("event source is created");
+
+    let onmessage =
+        Closure::<dyn FnMut(web_sys::MessageEvent)>::new(move |message: web_sys::MessageEvent| {
+            let comment = match message
+                .data()
+                .as_string()
+                .and_then(|data| serde_json::from_str::<types::RenderedComment>(&data).ok())
+            {
+                Some(comment) => comment,
+                None => return,
+            };
+            let document = match web_sys::window().and_then(|window| window.document()) {
+                Some(document) => document,
+                None => return,
+            };
+
+            // Comments are rendered by the server the same way as on page load, and replace the
+            // comment on the page. Thread replies arrive as their root with the whole thread.
+            if let Some(element) = document.get_element_by_id(&comment.event_id) {
+                element.set_outer_html(&comment.html);
+                return;
+            }
+
+            if append {
+                if let Some(live_comments) =
+                    document.query_selector(".live-comments").ok().flatten()
+                {
+                    let _ = live_comments.insert_adjacent_html("beforeend", &comment.html);
+                }
+            }
+        });
+    source.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+    onmessage.forget();
+
+    source
+}
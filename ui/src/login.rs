@@ -0,0 +1,53 @@
+use crate::AnyComponent;
+use east::{render_with_component, Markup, Render};
+
+pub struct Login {}
+
+impl Render<AnyComponent> for Login {
+    fn render(self) -> Markup {
+        render_with_component!(AnyComponent, {
+            div {
+                class: "row mb-3",
+                h3 {
+                    "Log in",
+                    small { "Sign in with your Matrix account" },
+                },
+            },
+            div {
+                class: "row",
+                div {
+                    class: "col-12",
+                    form {
+                        method: "post",
+                        div {
+                            class: "form-group",
+                            label { for_: "username", "Username" },
+                            input {
+                                type_: "text",
+                                class: "form-control",
+                                id: "username",
+                                name: "username",
+                                placeholder: "@user:example.org",
+                            },
+                        },
+                        div {
+                            class: "form-group",
+                            label { for_: "password", "Password" },
+                            input {
+                                type_: "password",
+                                class: "form-control",
+                                id: "password",
+                                name: "password",
+                            },
+                        },
+                        input {
+                            class: "btn btn-primary pull-right",
+                            type_: "submit",
+                            value: "Log in",
+                        },
+                    }
+                }
+            },
+        })
+    }
+}
@@ -0,0 +1,99 @@
+use crate::AnyComponent;
+use east::{render_from_dyn, rsx, use_state, Element, RenderDyn, Scope};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NewPostDialog {
+    pub action: String,
+}
+
+#[render_from_dyn]
+impl RenderDyn for NewPostDialog {
+    fn render_dyn(cx: Scope<Self>) -> Element {
+        let open = use_state(&cx, || false);
+        let hydrated = use_state(&cx, || false);
+
+        #[cfg(target_arch = "wasm32")]
+        cx.use_hook(|_| hydrated.set(true));
+
+        // Without scripts the form is shown in place of the dialog.
+        let (button_class, dialog_class) = match (*hydrated.get(), *open.get()) {
+            (false, _) => ("btn btn-primary d-none", ""),
+            (true, true) => ("btn btn-primary", "new-post-dialog"),
+            (true, false) => ("btn btn-primary", "new-post-dialog d-none"),
+        };
+        let cancel_class = if *hydrated.get() {
+            "btn btn-default"
+        } else {
+            "btn btn-default d-none"
+        };
+
+        cx.render(rsx! {
+            div {
+                class: "col-12",
+                button {
+                    r#type: "button",
+                    class: "{button_class}",
+                    onclick: move |_| open.set(true),
+                    "New topic",
+                },
+                div {
+                    class: "{dialog_class}",
+                    role: "dialog",
+                    div {
+                        class: "new-post-dialog-content",
+                        h5 { "New topic" },
+                        form {
+                            method: "post",
+                            action: "{cx.props.action}",
+                            input { r#type: "hidden", name: "action", value: "NewTopic" },
+                            div {
+                                class: "form-group",
+                                label { r#for: "new-topic-title", "Title" },
+                                input {
+                                    r#type: "text",
+                                    class: "form-control",
+                                    id: "new-topic-title",
+                                    name: "title",
+                                    required: "true",
+                                },
+                            },
+                            div {
+                                class: "form-group",
+                                label { r#for: "new-topic-topic", "Topic" },
+                                input {
+                                    r#type: "text",
+                                    class: "form-control",
+                                    id: "new-topic-topic",
+                                    name: "topic",
+                                },
+                            },
+                            div {
+                                class: "form-group",
+                                label { r#for: "new-topic-body", "First message (Markdown)" },
+                                textarea {
+                                    class: "form-control",
+                                    id: "new-topic-body",
+                                    name: "body",
+                                    rows: "8",
+                                    required: "true",
+                                },
+                            },
+                            button {
+                                r#type: "button",
+                                class: "{cancel_class}",
+                                onclick: move |_| open.set(false),
+                                "Cancel",
+                            },
+                            input {
+                                class: "btn btn-primary pull-right",
+                                r#type: "submit",
+                                value: "Create topic",
+                            },
+                        },
+                    },
+                },
+            }
+        })
+    }
+}
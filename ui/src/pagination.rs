@@ -0,0 +1,50 @@
+use crate::AnyComponent;
+use east::{render, render_with_component, Markup, Render};
+
+pub struct Pagination {
+    pub base_url: String,
+    pub page: usize,
+    pub total_pages: usize,
+}
+
+impl Render<AnyComponent> for Pagination {
+    fn render(self) -> Markup {
+        let base_url = self.base_url;
+        let separator = if base_url.contains('?') { '&' } else { '?' };
+
+        render_with_component!(AnyComponent, {
+            nav {
+                ul {
+                    class: "pagination",
+                    (self.page > 1).then(|| render! {
+                        li {
+                            class: "page-item",
+                            a {
+                                class: "page-link",
+                                href: format!("{}{}page={}", base_url, separator, self.page - 1),
+                                "Previous",
+                            }
+                        }
+                    }),
+                    li {
+                        class: "page-item disabled",
+                        span {
+                            class: "page-link",
+                            format!("Page {} of {}", self.page, self.total_pages),
+                        }
+                    },
+                    (self.page < self.total_pages).then(|| render! {
+                        li {
+                            class: "page-item",
+                            a {
+                                class: "page-link",
+                                href: format!("{}{}page={}", base_url, separator, self.page + 1),
+                                "Next",
+                            }
+                        }
+                    }),
+                }
+            }
+        })
+    }
+}
@@ -1,14 +1,31 @@
-use crate::AnyComponent;
+use crate::collapsible::LONG_COMMENT_LENGTH;
+use crate::{
+    format_timestamp, AnyComponent, CollapsibleComment, Composer, LiveComments, Pagination,
+};
 use east::{render, render_with_component, Markup, PreEscaped, Render};
 use morum_base::types;
 
 pub struct Post {
     pub post: types::Post,
-    pub comments: Vec<types::Comment>,
+    pub comments: types::Page<types::Comment>,
+    pub user: Option<String>,
 }
 
 impl Render<AnyComponent> for Post {
     fn render(self) -> Markup {
+        let new_comment = if self.user.is_some() {
+            render_with_component!(AnyComponent, { NewComment {} })
+        } else {
+            render! {
+                p {
+                    a { href: "/login", "Log in" },
+                    " to post a comment.",
+                }
+            }
+        };
+
+        let room_id = self.post.room_id.clone();
+
         render_with_component!(AnyComponent, {
             div {
                 class: "row mb-3",
@@ -19,9 +36,328 @@ impl Render<AnyComponent> for Post {
                         render! { small { t } }
                     })
                 },
+                p {
+                    class: "text-muted",
+                    format!(
+                        "Posted by {} on {} · {} comments",
+                        self.post.author,
+                        format_timestamp(self.post.creation_time),
+                        self.post.comment_count,
+                    ),
+                },
+            },
+
+            self.comments.items.into_iter().map(|comment| {
+                render_with_component!(AnyComponent, {
+                    Comment {
+                        room_id: room_id.clone(),
+                        comment: comment,
+                        user: self.user.clone(),
+                        expand_thread: false,
+                    }
+                })
+            }).collect::<Vec<_>>(),
+
+            LiveComments {
+                room_id: self.post.room_id.clone(),
+                append: self.comments.page == self.comments.total_pages,
+            },
+
+            div {
+                class: "row",
+                Pagination {
+                    base_url: format!("/post/{}", self.post.room_id),
+                    page: self.comments.page,
+                    total_pages: self.comments.total_pages,
+                },
+            },
+
+            div {
+                class: "row",
+                new_comment,
+            },
+
+            div {
+                class: "row",
+                a {
+                    "Post from a Matrix client",
+                    class: "btn btn-secondary",
+                    href: format!("https://matrix.to/#/{}", self.post.room_id),
+                },
+            },
+        })
+    }
+}
+
+pub struct Thread {
+    pub post: types::Post,
+    pub root: types::Comment,
+    pub user: Option<String>,
+}
+
+impl Render<AnyComponent> for Thread {
+    fn render(self) -> Markup {
+        render_with_component!(AnyComponent, {
+            div {
+                class: "row mb-3",
+                h3 {
+                    a {
+                        href: format!("/post/{}", self.post.room_id),
+                        self.post.title,
+                    },
+                    br { },
+                    small { format!("Thread · {} replies", self.root.thread_replies.len()) },
+                },
+            },
+
+            Comment {
+                room_id: self.post.room_id,
+                comment: self.root,
+                user: self.user,
+                expand_thread: true,
+            },
+        })
+    }
+}
+
+const THREAD_PREVIEW_REPLIES: usize = 3;
+
+pub struct Comment {
+    pub room_id: String,
+    pub comment: types::Comment,
+    pub user: Option<String>,
+    pub expand_thread: bool,
+}
+
+impl Render<AnyComponent> for Comment {
+    fn render(self) -> Markup {
+        let room_id = self.room_id;
+        let comment = self.comment;
+
+        let reply_quote =
+            comment
+                .in_reply_to
+                .zip(comment.reply_quote)
+                .map(|(in_reply_to, quote)| {
+                    render! {
+                        blockquote {
+                            class: "reply-quote",
+                            a {
+                                href: format!("/post/{}/comment/{}", room_id, in_reply_to),
+                                strong { quote.sender_name },
+                            },
+                            p { quote.snippet },
+                        }
+                    }
+                });
+
+        let history_url = format!("/post/{}/comment/{}/history", room_id, comment.event_id);
+        let reply_count = comment.thread_replies.len();
+        let thread_url = format!("/post/{}/thread/{}", room_id, comment.event_id);
+        let replies = comment
+            .thread_replies
+            .into_iter()
+            .map(|reply| {
+                render_with_component!(AnyComponent, {
+                    Comment {
+                        room_id: room_id.clone(),
+                        comment: reply,
+                        user: self.user.clone(),
+                        expand_thread: false,
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let body = match comment.redaction {
+            Some(redaction) => render! {
+                p {
+                    class: "card-text text-muted",
+                    em { "[deleted]" },
+                    redaction.reason.map(|reason| render! {
+                        span { format!(" ({})", reason) }
+                    }),
+                }
+            },
+            None => match comment.kind {
+                types::CommentKind::Message if comment.html.len() > LONG_COMMENT_LENGTH => {
+                    render_with_component!(AnyComponent, {
+                        CollapsibleComment { html: comment.html }
+                    })
+                }
+                types::CommentKind::Message => render! {
+                    div {
+                        class: "card-text",
+                        PreEscaped(comment.html),
+                    }
+                },
+                types::CommentKind::Notice => render! {
+                    div {
+                        class: "card-text notice",
+                        PreEscaped(comment.html),
+                    }
+                },
+                types::CommentKind::Emote => render! {
+                    div {
+                        class: "card-text emote",
+                        "* ",
+                        comment.sender_name.clone(),
+                        " ",
+                        PreEscaped(comment.html),
+                    }
+                },
+                types::CommentKind::Location {
+                    description,
+                    geo_uri,
+                    map_url,
+                } => {
+                    // The URI comes from the sender, so it is only linked when it is a geo URI.
+                    let href =
+                        map_url.or_else(|| geo_uri.starts_with("geo:").then(|| geo_uri.clone()));
+                    match href {
+                        Some(href) => render! {
+                            p {
+                                class: "card-text location",
+                                "📍 ",
+                                a {
+                                    href: href,
+                                    title: geo_uri,
+                                    description,
+                                },
+                            }
+                        },
+                        None => render! {
+                            p {
+                                class: "card-text location",
+                                "📍 ",
+                                description,
+                                " (",
+                                geo_uri,
+                                ")",
+                            }
+                        },
+                    }
+                }
+                types::CommentKind::Poll(poll) => {
+                    render_with_component!(AnyComponent, { Poll { poll: poll } })
+                }
+            },
+        };
+
+        let reactions = render_with_component!(AnyComponent, {
+            Reactions {
+                room_id: room_id.clone(),
+                event_id: comment.event_id.clone(),
+                reactions: comment.reactions,
+                user: self.user,
+            }
+        });
+
+        let thread = if reply_count == 0 {
+            None
+        } else if self.expand_thread || reply_count <= THREAD_PREVIEW_REPLIES {
+            Some(render! {
+                div {
+                    class: "thread",
+                    replies,
+                }
+            })
+        } else {
+            Some(render! {
+                details {
+                    class: "thread",
+                    summary {
+                        format!("{} replies", reply_count),
+                        " · ",
+                        a { href: thread_url, "View thread" },
+                    },
+                    replies,
+                }
+            })
+        };
+
+        render! {
+            div {
+                class: "row",
+                id: comment.event_id,
+                div {
+                    class: "card",
+                    p {
+                        class: "card-text",
+                        comment.sender_avatar_url.map(|avatar_url| render! {
+                            img {
+                                class: "avatar",
+                                src: avatar_url,
+                                alt: "",
+                                width: "24",
+                                height: "24",
+                            },
+                            " ",
+                        }),
+                        strong { title: comment.sender, comment.sender_name },
+                        " ",
+                        small {
+                            class: "text-muted",
+                            format_timestamp(comment.origin_server_ts),
+                        },
+                        comment.last_edit_time.map(|last_edit_time| render! {
+                            small {
+                                class: "text-muted",
+                                " ",
+                                a {
+                                    href: history_url,
+                                    title: format!("Last edited {}", format_timestamp(last_edit_time)),
+                                    "(edited)",
+                                },
+                            }
+                        }),
+                    },
+                    reply_quote,
+                    div { class: "comment-body", body },
+                    reactions,
+                    thread,
+                },
+                hr { }
+            }
+        }
+    }
+}
+
+pub struct CommentHistory {
+    pub post: types::Post,
+    pub event_id: String,
+    pub revisions: Vec<(types::Revision, Vec<types::DiffLine>)>,
+}
+
+impl Render<AnyComponent> for CommentHistory {
+    fn render(self) -> Markup {
+        let revision_count = self.revisions.len();
+
+        render_with_component!(AnyComponent, {
+            div {
+                class: "row mb-3",
+                h3 {
+                    a {
+                        href: format!("/post/{}", self.post.room_id),
+                        self.post.title,
+                    },
+                    br { },
+                    small { format!("Edit history · {} revisions", revision_count) },
+                },
+                p {
+                    a {
+                        href: format!("/post/{}/comment/{}", self.post.room_id, self.event_id),
+                        "Back to comment",
+                    },
+                },
             },
 
-            self.comments.into_iter().map(|comment| {
+            self.revisions.into_iter().enumerate().map(|(i, (revision, diff))| {
+                let label = if i == 0 {
+                    "Original".to_owned()
+                } else {
+                    format!("Revision {}", i)
+                };
+
                 render! {
                     div {
                         class: "row",
@@ -29,26 +365,203 @@ impl Render<AnyComponent> for Post {
                             class: "card",
                             p {
                                 class: "card-text",
-                                strong { comment.sender }
+                                strong { label },
+                                " ",
+                                small {
+                                    class: "text-muted",
+                                    format_timestamp(revision.origin_server_ts),
+                                },
+                            },
+                            pre {
+                                class: "diff",
+                                diff.into_iter().map(|line| {
+                                    let (class, prefix) = match line.tag {
+                                        types::DiffTag::Equal => ("diff-equal", "  "),
+                                        types::DiffTag::Delete => ("diff-delete", "- "),
+                                        types::DiffTag::Insert => ("diff-insert", "+ "),
+                                    };
+
+                                    render! {
+                                        span { class: class, prefix, line.text }
+                                    }
+                                }).collect::<Vec<_>>(),
                             },
-                            p {
-                                class: "card-text",
-                                PreEscaped(comment.html),
-                            }
                         },
                         hr { }
                     }
                 }
             }).collect::<Vec<_>>(),
+        })
+    }
+}
+
+pub struct Poll {
+    pub poll: types::Poll,
+}
+
+impl Render<AnyComponent> for Poll {
+    fn render(self) -> Markup {
+        let voters = self.poll.voters;
 
+        render! {
             div {
-                class: "row",
-                a {
-                    "Post a new comment",
-                    class: "btn btn-primary",
-                    href: format!("https://matrix.to/#/{}", self.post.room_id),
+                class: "card-text poll",
+                p { strong { self.poll.question } },
+                ul {
+                    self.poll.answers.into_iter().map(|answer| {
+                        let percent = if voters == 0 {
+                            0
+                        } else {
+                            answer.votes * 100 / voters
+                        };
+
+                        render! {
+                            li {
+                                answer.text,
+                                " ",
+                                small {
+                                    class: "text-muted",
+                                    format!("{} votes ({}%)", answer.votes, percent),
+                                },
+                                div {
+                                    class: "progress",
+                                    div {
+                                        class: "progress-bar",
+                                        style: format!("width: {}%", percent),
+                                    },
+                                },
+                            }
+                        }
+                    }).collect::<Vec<_>>(),
                 },
-            },
+                p {
+                    class: "text-muted",
+                    format!("{} voters", voters),
+                },
+            }
+        }
+    }
+}
+
+pub struct Reactions {
+    pub room_id: String,
+    pub event_id: String,
+    pub reactions: Vec<types::Reaction>,
+    pub user: Option<String>,
+}
+
+impl Render<AnyComponent> for Reactions {
+    fn render(self) -> Markup {
+        let action = format!("/post/{}", self.room_id);
+        let event_id = self.event_id;
+
+        let user = match self.user {
+            Some(user) => user,
+            None => {
+                return render! {
+                    div {
+                        class: "reactions",
+                        self.reactions.into_iter().map(|reaction| {
+                            let senders = reaction
+                                .senders
+                                .iter()
+                                .map(|sender| sender.user_id.as_str())
+                                .collect::<Vec<_>>()
+                                .join(", ");
+
+                            render! {
+                                span {
+                                    class: "reaction",
+                                    title: senders,
+                                    format!("{} {}", reaction.key, reaction.count()),
+                                }
+                            }
+                        }).collect::<Vec<_>>(),
+                    }
+                };
+            }
+        };
+
+        render! {
+            div {
+                class: "reactions",
+                self.reactions.into_iter().map(|reaction| {
+                    let senders = reaction
+                        .senders
+                        .iter()
+                        .map(|sender| sender.user_id.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let (form_action, class) = if reaction.reacted_by(&user) {
+                        ("Unreact", "btn btn-sm btn-primary")
+                    } else {
+                        ("React", "btn btn-sm btn-default")
+                    };
+
+                    render! {
+                        form {
+                            class: "reaction",
+                            method: "post",
+                            action: action.clone(),
+                            input { type_: "hidden", name: "action", value: form_action },
+                            input { type_: "hidden", name: "event_id", value: event_id.clone() },
+                            input { type_: "hidden", name: "key", value: reaction.key.clone() },
+                            input {
+                                class: class,
+                                type_: "submit",
+                                title: senders,
+                                value: format!("{} {}", reaction.key, reaction.count()),
+                            },
+                        }
+                    }
+                }).collect::<Vec<_>>(),
+                form {
+                    class: "reaction",
+                    method: "post",
+                    action: action.clone(),
+                    input { type_: "hidden", name: "action", value: "React" },
+                    input { type_: "hidden", name: "event_id", value: event_id.clone() },
+                    input {
+                        class: "reaction-key",
+                        type_: "text",
+                        name: "key",
+                        size: "2",
+                        placeholder: "👍",
+                        title: "Reaction",
+                    },
+                    input { class: "btn btn-sm btn-default", type_: "submit", value: "React" },
+                },
+            }
+        }
+    }
+}
+
+pub struct NewComment {}
+
+impl Render<AnyComponent> for NewComment {
+    fn render(self) -> Markup {
+        render_with_component!(AnyComponent, {
+            div {
+                class: "col-12",
+                form {
+                    method: "post",
+                    input {
+                        type_: "hidden",
+                        name: "action",
+                        value: "NewComment",
+                    },
+                    Composer {
+                        id: "body".to_owned(),
+                        label: "Comment (Markdown)".to_owned(),
+                        rows: 5,
+                    },
+                    input {
+                        class: "btn btn-primary pull-right",
+                        type_: "submit",
+                        value: "Post comment",
+                    },
+                }
+            }
         })
     }
 }
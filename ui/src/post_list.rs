@@ -1,14 +1,32 @@
-use crate::AnyComponent;
+use crate::{format_timestamp, AnyComponent, NewPostDialog, Pagination};
 use east::{render, render_with_component, Markup, Render};
 use morum_base::types;
 
 pub struct PostList {
     pub category: types::Category,
-    pub posts: Vec<types::Post>,
+    pub posts: types::Page<types::Post>,
+    pub sort: types::PostSort,
+    pub logged_in: bool,
 }
 
 impl Render<AnyComponent> for PostList {
     fn render(self) -> Markup {
+        let base_url = format!("/category/{}", self.category.room_local_id);
+        let new_topic = if self.logged_in {
+            render_with_component!(AnyComponent, {
+                NewPostDialog {
+                    action: base_url.clone(),
+                }
+            })
+        } else {
+            render! {
+                p {
+                    a { href: "/login", "Log in" },
+                    " to start a new topic.",
+                }
+            }
+        };
+
         render_with_component!(AnyComponent, {
             div {
                 class: "row mb-3",
@@ -18,7 +36,32 @@ impl Render<AnyComponent> for PostList {
                 },
             },
 
-            self.posts.into_iter().map(|post| render! {
+            div {
+                class: "row mb-3",
+                ul {
+                    class: "nav nav-pills",
+                    types::PostSort::ALL.into_iter().map(|sort| {
+                        let link_class = if sort == self.sort {
+                            "nav-link active"
+                        } else {
+                            "nav-link"
+                        };
+
+                        render! {
+                            li {
+                                class: "nav-item",
+                                a {
+                                    class: link_class,
+                                    href: format!("{}?sort={}", base_url, sort.as_str()),
+                                    sort.label(),
+                                }
+                            }
+                        }
+                    }).collect::<Vec<_>>(),
+                },
+            },
+
+            self.posts.items.into_iter().map(|post| render! {
                 div {
                     class: "row",
                     div {
@@ -33,11 +76,34 @@ impl Render<AnyComponent> for PostList {
                         post.topic.map(|topic| render! {
                             p { class: "card-text", topic }
                         }),
+                        p {
+                            class: "card-text text-muted",
+                            format!(
+                                "{} · {} comments · last activity {}",
+                                post.author,
+                                post.comment_count,
+                                format_timestamp(post.last_activity_time),
+                            ),
+                        },
                         hr { },
                     },
                 },
             }).collect::<Vec<_>>(),
 
+            div {
+                class: "row",
+                Pagination {
+                    base_url: format!("{}?sort={}", base_url, self.sort.as_str()),
+                    page: self.posts.page,
+                    total_pages: self.posts.total_pages,
+                },
+            },
+
+            div {
+                class: "row mb-3",
+                new_topic,
+            },
+
             div {
                 class: "row",
                 NewPost { },
@@ -0,0 +1,157 @@
+use crate::{format_timestamp, AnyComponent, Pagination};
+use east::{render, render_with_component, Markup, Render};
+use morum_base::types;
+
+pub struct Search {
+    pub query: String,
+    pub category: String,
+    pub author: String,
+    pub categories: Vec<types::Category>,
+    pub results: types::Page<types::SearchResult>,
+    pub base_url: String,
+}
+
+impl Render<AnyComponent> for Search {
+    fn render(self) -> Markup {
+        let selected_category = self.category;
+        let has_query = !self.query.trim().is_empty();
+
+        let results = if self.results.items.is_empty() {
+            let message = if has_query {
+                "No results."
+            } else {
+                "Enter a search term above."
+            };
+
+            render! {
+                div {
+                    class: "row",
+                    p { class: "text-muted", message },
+                }
+            }
+        } else {
+            render_with_component!(AnyComponent, {
+                self.results.items.into_iter().map(|result| {
+                    let url = match &result.event_id {
+                        Some(event_id) => format!("/post/{}/comment/{}", result.room_id, event_id),
+                        None => format!("/post/{}", result.room_id),
+                    };
+
+                    render! {
+                        div {
+                            class: "row",
+                            div {
+                                class: "card",
+                                h5 {
+                                    class: "card-title",
+                                    a { href: url, result.post_title },
+                                },
+                                p {
+                                    class: "card-text text-muted",
+                                    result.category.map(|category| render! {
+                                        a {
+                                            href: format!("/category/{}", category.room_local_id),
+                                            category.title,
+                                        },
+                                        " · ",
+                                    }),
+                                    span { title: result.sender, result.sender_name },
+                                    " · ",
+                                    format_timestamp(result.origin_server_ts),
+                                },
+                                p {
+                                    class: "card-text",
+                                    result.snippet,
+                                },
+                            },
+                            hr { },
+                        }
+                    }
+                }).collect::<Vec<_>>(),
+
+                div {
+                    class: "row",
+                    Pagination {
+                        base_url: self.base_url,
+                        page: self.results.page,
+                        total_pages: self.results.total_pages,
+                    },
+                },
+            })
+        };
+
+        render_with_component!(AnyComponent, {
+            div {
+                class: "row mb-3",
+                h3 { "Search" },
+            },
+
+            div {
+                class: "row mb-3",
+                form {
+                    class: "col-12",
+                    method: "get",
+                    action: "/search",
+                    div {
+                        class: "form-group",
+                        label { for_: "q", "Search terms" },
+                        input {
+                            class: "form-control",
+                            type_: "text",
+                            id: "q",
+                            name: "q",
+                            value: self.query,
+                        },
+                    },
+                    div {
+                        class: "form-group",
+                        label { for_: "category", "Category" },
+                        select {
+                            class: "form-control",
+                            id: "category",
+                            name: "category",
+                            option { value: "", "All categories" },
+                            self.categories.into_iter().map(|category| {
+                                if category.room_local_id == selected_category {
+                                    render! {
+                                        option {
+                                            value: category.room_local_id,
+                                            selected: "selected",
+                                            category.title,
+                                        }
+                                    }
+                                } else {
+                                    render! {
+                                        option {
+                                            value: category.room_local_id,
+                                            category.title,
+                                        }
+                                    }
+                                }
+                            }).collect::<Vec<_>>(),
+                        },
+                    },
+                    div {
+                        class: "form-group",
+                        label { for_: "author", "Author" },
+                        input {
+                            class: "form-control",
+                            type_: "text",
+                            id: "author",
+                            name: "author",
+                            placeholder: "Display name or @user:server",
+                            value: self.author,
+                        },
+                    },
+                    input {
+                        class: "btn btn-primary",
+                        type_: "submit",
+                        value: "Search",
+                    },
+                },
+            },
+
+            results,
+        })
+    }
+}